@@ -3,6 +3,7 @@ use Morphing::color::Color;
 use Morphing::figure::merged_object::MergedObject;
 use Morphing::figure::object::Object;
 use Morphing::figure::projection::Projection;
+use Morphing::figure::triangle::Triangle;
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let color = Color::new([0; 4]);
@@ -10,55 +11,55 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("Морфинг");
     group.sample_size(10);
 
-    // let st = Object::load("./models/cube.obj", color.clone()).unwrap();
-    // let st = Projection::new(st, radius);
+    let st = Object::load("./models/cube.obj", color.clone()).unwrap();
+    let st = Projection::new(st, radius);
 
-    // let en = Object::load("./models/cube.obj", color.clone()).unwrap();
-    // let en = Projection::new(en, radius);
+    let en = Object::load("./models/cube.obj", color.clone()).unwrap();
+    let en = Projection::new(en, radius);
 
-    // group.bench_with_input("Куб", &(st, en), |b, (st, en)| {
-    //     b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
-    // });
+    group.bench_with_input("Куб", &(st, en), |b, (st, en)| {
+        b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
+    });
 
-    // let st = Object::load("./models/cylinder.obj", color.clone()).unwrap();
-    // let st = Projection::new(st, radius);
+    let st = Object::load("./models/cylinder.obj", color.clone()).unwrap();
+    let st = Projection::new(st, radius);
 
-    // let en = Object::load("./models/cylinder.obj", color.clone()).unwrap();
-    // let en = Projection::new(en, radius);
+    let en = Object::load("./models/cylinder.obj", color.clone()).unwrap();
+    let en = Projection::new(en, radius);
 
-    // group.bench_with_input("Цилиндр", &(st, en), |b, (st, en)| {
-    //     b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
-    // });
+    group.bench_with_input("Цилиндр", &(st, en), |b, (st, en)| {
+        b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
+    });
 
-    // let st = Object::load("./models/head.obj", color.clone()).unwrap();
-    // let st = Projection::new(st, radius);
+    let st = Object::load("./models/head.obj", color.clone()).unwrap();
+    let st = Projection::new(st, radius);
 
-    // let en = Object::load("./models/head.obj", color.clone()).unwrap();
-    // let en = Projection::new(en, radius);
+    let en = Object::load("./models/head.obj", color.clone()).unwrap();
+    let en = Projection::new(en, radius);
 
-    // group.bench_with_input("Голова", &(st, en), |b, (st, en)| {
-    //     b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
-    // });
+    group.bench_with_input("Голова", &(st, en), |b, (st, en)| {
+        b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
+    });
 
-    // let st = Object::load("./models/cube.obj", color.clone()).unwrap();
-    // let st = Projection::new(st, radius);
+    let st = Object::load("./models/cube.obj", color.clone()).unwrap();
+    let st = Projection::new(st, radius);
 
-    // let en = Object::load("./models/cylinder.obj", color.clone()).unwrap();
-    // let en = Projection::new(en, radius);
+    let en = Object::load("./models/cylinder.obj", color.clone()).unwrap();
+    let en = Projection::new(en, radius);
 
-    // group.bench_with_input("Куб+цилиндр", &(st, en), |b, (st, en)| {
-    //     b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
-    // });
+    group.bench_with_input("Куб+цилиндр", &(st, en), |b, (st, en)| {
+        b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
+    });
 
-    // let st = Object::load("./models/cube.obj", color.clone()).unwrap();
-    // let st = Projection::new(st, radius);
+    let st = Object::load("./models/cube.obj", color.clone()).unwrap();
+    let st = Projection::new(st, radius);
 
-    // let en = Object::load("./models/radish.obj", color.clone()).unwrap();
-    // let en = Projection::new(en, radius);
+    let en = Object::load("./models/radish.obj", color.clone()).unwrap();
+    let en = Projection::new(en, radius);
 
-    // group.bench_with_input("Куб+редиска", &(st, en), |b, (st, en)| {
-    //     b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
-    // });
+    group.bench_with_input("Куб+редиска", &(st, en), |b, (st, en)| {
+        b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
+    });
 
     let st = Object::load("./models/cylinder.obj", color.clone()).unwrap();
     let st = Projection::new(st, radius);
@@ -70,15 +71,63 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
     });
 
-    // let st = Object::load("./models/cylinder.obj", color.clone()).unwrap();
-    // let st = Projection::new(st, radius);
+    let st = Object::load("./models/cylinder.obj", color.clone()).unwrap();
+    let st = Projection::new(st, radius);
 
-    // let en = Object::load("./models/head.obj", color.clone()).unwrap();
-    // let en = Projection::new(en, radius);
+    let en = Object::load("./models/head.obj", color.clone()).unwrap();
+    let en = Projection::new(en, radius);
+
+    group.bench_with_input("Цилиндр+голова", &(st, en), |b, (st, en)| {
+        b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
+    });
+
+    group.finish();
+
+    // Rays through every vertex of a sphere-projected model
+    let mut group = c.benchmark_group("Проекция со сферы");
+    group.sample_size(10);
 
-    // group.bench_with_input("Цилиндр+голова", &(st, en), |b, (st, en)| {
-    //     b.iter(|| MergedObject::new(black_box(st.clone()), black_box(en.clone())));
-    // });
+    for (name, path) in [
+        ("Голова", "./models/head.obj"),
+        ("Редиска", "./models/radish.obj"),
+    ] {
+        let obj = Object::load(path, color.clone()).unwrap();
+        let proj = Projection::new(obj.clone(), radius);
+
+        group.bench_with_input(name, &proj, |b, proj| {
+            b.iter(|| {
+                for i in 0..proj.nvertexes() {
+                    let _ = black_box(proj.project_from_sphere(proj.sphere_vertex(i)));
+                }
+            });
+        });
+
+        // the same rays tested against every face, as before the BVH
+        let triangles = (0..obj.nfaces())
+            .map(|ind| {
+                let coords = obj.face_coords(ind);
+                Triangle::new(coords[0], coords[1], coords[2])
+            })
+            .collect::<Vec<_>>();
+        let origin = obj.projection_center();
+        group.bench_with_input(
+            format!("{name}, перебор граней"),
+            &(proj, triangles),
+            |b, (proj, triangles)| {
+                b.iter(|| {
+                    for i in 0..proj.nvertexes() {
+                        let v = proj.sphere_vertex(i);
+                        let _ = black_box(
+                            triangles
+                                .iter()
+                                .find_map(|tri| tri.intersect(origin, origin + v)),
+                        );
+                    }
+                });
+            },
+        );
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,178 @@
+use super::task::BlendTask;
+use super::{Mode, Painting};
+
+use std::time::Duration;
+
+use crate::egui::{Button, Context, Slider, Ui, Window};
+use crate::figure::blend_shape::BlendError;
+use crate::DEFAULT_NOTIFY_DURATION;
+
+impl Painting {
+    // Targets mixed into the start model with their own weights, as blend
+    // shapes of a face with several expressions
+    pub fn ui_blend(&mut self, ctx: &Context) {
+        let mut is_open = self.is_blend_open;
+        Window::new("Смешивание форм")
+            .open(&mut is_open)
+            .resizable(false)
+            .show(ctx, |ui| self.blend_contents(ui));
+        self.is_blend_open = is_open;
+    }
+
+    pub fn poll_blend_task(&mut self) {
+        if !self
+            .blend_task
+            .as_ref()
+            .is_some_and(|task| task.is_finished())
+        {
+            return;
+        }
+        let task = self.blend_task.take().unwrap();
+        let is_cancelled = task.is_cancelled();
+        if !is_cancelled {
+            for warning in task.take_warnings() {
+                self.toasts
+                    .warning(warning)
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+        }
+        let result = task.join().unwrap_or_else(|error| {
+            Err(BlendError {
+                target: None,
+                error,
+            })
+        });
+        match result {
+            _ if is_cancelled => {
+                self.toasts
+                    .info("Смешивание отменено")
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+            Ok(shape) => {
+                self.blend_shape = Some(shape);
+                self.show_blend();
+            }
+            Err(e) => {
+                self.toasts
+                    .error(format!("Смешивание невозможно: {e}"))
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+        }
+    }
+
+    pub fn blend_weights(&self) -> Vec<f64> {
+        self.blend_targets.iter().map(|(_, _, w)| *w).collect()
+    }
+}
+
+impl Painting {
+    fn blend_contents(&mut self, ui: &mut Ui) {
+        ui.label("Базовая модель — стартовый объект");
+        if self.blend_targets.is_empty() {
+            ui.label("Добавьте цели — модели той же формы с другими выражениями");
+        }
+        let is_built = self.blend_shape.is_some();
+        let mut is_changed = false;
+        let mut removed = None;
+        for (i, (name, _, weight)) in self.blend_targets.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                is_changed |= ui
+                    .add_enabled(is_built, Slider::new(weight, 0.0..=1.0).text(name.as_str()))
+                    .changed();
+                if ui.button("Удалить").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if is_changed {
+            self.show_blend();
+        }
+        if let Some(i) = removed {
+            self.blend_targets.remove(i);
+            self.drop_blend_shape();
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Добавить цель...").clicked() {
+                if let Some((name, object)) = self.pick_model() {
+                    self.blend_targets.push((name, object, 0.));
+                    self.drop_blend_shape();
+                }
+            }
+            if let Some(obj) = &self.result_obj {
+                if ui.button("Добавить итоговый объект").clicked() {
+                    self.blend_targets
+                        .push(("итоговый объект".to_string(), obj.clone(), 0.));
+                    self.drop_blend_shape();
+                }
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let is_ready = self.start_obj.is_some()
+                && !self.blend_targets.is_empty()
+                && !self.is_task_running();
+            if ui.add_enabled(is_ready, Button::new("Построить")).clicked() {
+                self.build_blend();
+            }
+            if ui
+                .add_enabled(is_built, Button::new("Сбросить веса"))
+                .clicked()
+            {
+                for (_, _, weight) in self.blend_targets.iter_mut() {
+                    *weight = 0.;
+                }
+                self.show_blend();
+            }
+        });
+    }
+
+    // Models which are not star-shaped are reported by the worker, as in the morph of two models
+    fn build_blend(&mut self) {
+        let base = self.start_obj.clone().unwrap();
+        for (name, obj, _) in self.blend_targets.iter() {
+            if obj.landmarks().len() != base.landmarks().len() {
+                self.toasts
+                    .warning(format!("{name}: число точек соответствия отличается от базовой модели, лишние точки не учитываются"))
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+        }
+
+        let targets = self
+            .blend_targets
+            .iter()
+            .map(|(name, obj, _)| (name.clone(), obj.clone()))
+            .collect();
+        self.blend_task = Some(BlendTask::spawn_blend(
+            ("Стартовая модель".to_string(), base),
+            targets,
+            self.mapping,
+            self.is_auto_align,
+        ));
+    }
+
+    fn show_blend(&mut self) {
+        self.close_morph();
+        self.mode = Mode::Blending;
+        self.draw_object();
+    }
+
+    // The targets changed, the common mesh has to be built again
+    fn drop_blend_shape(&mut self) {
+        self.blend_shape = None;
+        if let Mode::Blending = self.mode {
+            self.mode = Mode::StartObjView;
+            self.draw_object();
+        }
+    }
+}
@@ -1,20 +1,45 @@
-use eframe::egui::{Modifiers, PointerButton, Vec2};
+use eframe::egui::{Modifiers, PointerButton, Pos2, Vec2};
 
 use super::Painting;
 
 impl Painting {
     pub fn mouse_moved(&mut self, pos: &Vec2) {
         if self.is_movement_access {
-            self.move_object(pos);
+            if self.is_center_drag {
+                self.move_projection_center(pos);
+            } else {
+                self.move_object(pos);
+            }
         }
         if self.is_rotating_access {
             self.rotate_object(pos);
         }
     }
 
-    pub fn pointer_button(&mut self, button: PointerButton, pressed: bool, modifiers: Modifiers) {
+    pub fn pointer_moved(&mut self, pos: &Pos2) {
+        if self.is_painting_access {
+            self.paint_mask(pos);
+        }
+    }
+
+    pub fn pointer_button(
+        &mut self,
+        pos: &Pos2,
+        button: PointerButton,
+        pressed: bool,
+        modifiers: Modifiers,
+    ) {
         match button {
-            PointerButton::Primary => self.is_movement_access = pressed && modifiers.ctrl,
+            PointerButton::Primary => {
+                self.is_movement_access = pressed && modifiers.ctrl;
+                if pressed && modifiers.shift {
+                    self.pick_landmark(pos);
+                }
+                self.is_painting_access = pressed && modifiers.alt && self.is_mask_painting;
+                if self.is_painting_access {
+                    self.paint_mask(pos);
+                }
+            }
             PointerButton::Secondary => self.is_rotating_access = pressed && modifiers.ctrl,
             _ => {}
         }
@@ -1,5 +1,7 @@
 use super::{Mode, Painting};
 
+use crate::figure::progress::{Phase, Progress};
+
 impl Painting {
     pub fn button_load_start_label(&self) -> &'static str {
         if self.start_obj.is_some() {
@@ -30,4 +32,19 @@ impl Painting {
             _ => "Итоговый объект",
         }
     }
+
+    pub fn morph_phase_label(progress: &Progress) -> &'static str {
+        if progress.is_cancelled() {
+            return "Отмена...";
+        }
+        match progress.phase() {
+            Phase::StarCheck => "Проверка звёздности",
+            Phase::Projection => "Проецирование на сферу",
+            Phase::Alignment => "Выравнивание поворотом",
+            Phase::Overlay => "Наложение рёбер",
+            Phase::BackProjection => "Обратное проецирование",
+            Phase::FaceResolution => "Построение граней",
+            Phase::Trajectory => "Жёсткие траектории",
+        }
+    }
 }
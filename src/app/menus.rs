@@ -1,11 +1,21 @@
+use super::task::CenterTask;
 use super::{Mode, Painting};
 
 use std::mem::swap;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::color::Color;
-use crate::egui::{widgets::color_picker, Ui};
+use crate::egui::{widgets::color_picker, Button, DragValue, ProgressBar, Slider, Ui};
+use crate::figure::kernel::Kernel;
 use crate::figure::object::Object;
+use crate::figure::projection::Mapping;
+use crate::figure::ratio_mask::{Region, Timing};
+use crate::figure::trajectory::Trajectory;
 use crate::figure::vertex::Vertex;
+use crate::playback::{EasingKind, Repeat};
+use crate::texture::{Filter, Texture, Wrap};
+use crate::DEFAULT_NOTIFY_DURATION;
 use eframe::egui::color_picker::Alpha;
 use rfd::FileDialog;
 
@@ -19,11 +29,95 @@ impl Painting {
                 self.view_nested_menus(ui)
             });
             ui.menu_button("Выбор цвета", |ui| self.pick_color(ui));
+            ui.menu_button("Текстура", |ui| self.texture_nested_menus(ui));
             ui.menu_button(
                 "Перемещение источника света",
                 |ui| self.move_light_src_nested_menus(ui),
             );
-            ui.menu_button("Морфинг", |ui| self.morph(ui));
+            ui.menu_button("Морфинг", |ui| {
+                self.check_models(ui);
+                ui.menu_button("Отображение на сферу", |ui| {
+                    self.mapping_nested_menus(ui)
+                });
+                ui.menu_button("Центр проекции", |ui| {
+                    self.projection_center_nested_menus(ui)
+                });
+                ui.menu_button("Точки соответствия", |ui| {
+                    self.landmarks_nested_menus(ui)
+                });
+                ui.checkbox(&mut self.is_auto_align, "Выравнивать итоговую модель поворотом");
+                ui.menu_button("Траектории вершин", |ui| {
+                    self.trajectory_nested_menus(ui)
+                });
+                ui.menu_button("Локальный морфинг", |ui| {
+                    self.local_morph_nested_menus(ui)
+                });
+                ui.menu_button("Воспроизведение", |ui| {
+                    self.playback_nested_menus(ui)
+                });
+                self.morph(ui);
+                self.save_current_obj(ui);
+                self.save_morph_gltf(ui);
+                ui.checkbox(&mut self.is_playlist_open, "Цепочка моделей");
+                ui.checkbox(&mut self.is_blend_open, "Смешивание форм");
+            });
+        });
+    }
+
+    pub fn ui_morph_progress(&mut self, ui: &mut Ui) {
+        let (progress, link, nlinks) = match (&self.morph_task, &self.blend_task) {
+            (Some(task), _) => (task.progress(), task.link(), task.nlinks()),
+            (_, Some(task)) => (task.progress(), task.link(), task.nlinks()),
+            _ => return,
+        };
+        ui.horizontal(|ui| {
+            if nlinks > 1 {
+                ui.label(format!("Переход {} из {}:", link + 1, nlinks));
+            }
+            ui.label(Self::morph_phase_label(progress));
+            ui.add(ProgressBar::new(progress.fraction()).show_percentage());
+            if ui
+                .add_enabled(!progress.is_cancelled(), Button::new("Отмена"))
+                .clicked()
+            {
+                progress.cancel();
+            }
+        });
+    }
+
+    // Timeline of the played morph
+    pub fn ui_playback(&mut self, ui: &mut Ui) {
+        if self.morph_chain.is_none() {
+            return;
+        }
+        ui.horizontal(|ui| {
+            let label = if self.playback.is_playing() {
+                "Пауза"
+            } else {
+                "Воспроизвести"
+            };
+            if ui.button(label).clicked() {
+                if self.playback.is_playing() {
+                    self.playback.pause();
+                } else {
+                    self.playback.play();
+                }
+            }
+            if ui.button("В начало").clicked() {
+                self.playback.seek(0.);
+            }
+            ui.spacing_mut().slider_width = 300.;
+            let mut progress = self.playback.ratio();
+            if ui
+                .add(Slider::new(&mut progress, 0.0..=1.0).text("Доля морфинга"))
+                .changed()
+            {
+                self.playback.seek(progress);
+            }
+            if ui.button("Закрыть").clicked() {
+                self.close_morph();
+                self.draw_object();
+            }
         });
     }
 
@@ -50,6 +144,281 @@ impl Painting {
         }
     }
 
+    fn texture_nested_menus(&mut self, ui: &mut Ui) {
+        ui.set_max_width(200.0); // To make sure we wrap long text
+
+        if ui.button("Загрузить текстуру...").clicked() {
+            if let Some(filename) = FileDialog::new()
+                .add_filter("image", &["png", "jpg", "jpeg"])
+                .pick_file()
+            {
+                match Texture::load(filename) {
+                    Ok(texture) => {
+                        let object = match self.mode {
+                            Mode::StartObjView => &mut self.start_obj,
+                            Mode::ResultObjView => &mut self.result_obj,
+                            _ => &mut None,
+                        };
+                        if let Some(object) = object {
+                            object.set_texture(Some(Arc::new(texture)));
+                            self.draw_object();
+                        }
+                    }
+                    Err(e) => {
+                        self.toasts
+                            .error(format!("Ошибка загрузки текстуры: {e}"))
+                            .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                            .closable(true)
+                            .show_progress_bar(true);
+                    }
+                }
+            }
+        }
+        if ui.button("Убрать текстуру").clicked() {
+            let object = match self.mode {
+                Mode::StartObjView => &mut self.start_obj,
+                Mode::ResultObjView => &mut self.result_obj,
+                _ => &mut None,
+            };
+            if let Some(object) = object {
+                object.set_texture(None);
+                self.draw_object();
+            }
+        }
+
+        ui.separator();
+        let mut sampler = self.canvas.sampler();
+        ui.radio_value(&mut sampler.filter, Filter::Nearest, "Ближайший тексель");
+        ui.radio_value(&mut sampler.filter, Filter::Bilinear, "Билинейная фильтрация");
+        ui.separator();
+        ui.radio_value(&mut sampler.wrap, Wrap::Repeat, "Повторение");
+        ui.radio_value(&mut sampler.wrap, Wrap::Clamp, "Ограничение краем");
+        if sampler != self.canvas.sampler() {
+            self.canvas.set_sampler(sampler);
+            self.draw_object();
+        }
+    }
+
+    fn mapping_nested_menus(&mut self, ui: &mut Ui) {
+        ui.set_max_width(250.0); // To make sure we wrap long text
+
+        ui.radio_value(&mut self.mapping, Mapping::Central, "Центральная проекция");
+        ui.radio_value(
+            &mut self.mapping,
+            Mapping::Harmonic,
+            "Гармоническое (для замкнутых не звёздных моделей)",
+        );
+    }
+
+    fn trajectory_nested_menus(&mut self, ui: &mut Ui) {
+        ui.set_max_width(250.0); // To make sure we wrap long text
+
+        let radios = [
+            (Trajectory::Linear, "Линейные"),
+            (Trajectory::Spherical, "Сферические (радиус и направление отдельно)"),
+            (Trajectory::Rigid, "Как можно более жёсткие"),
+        ];
+        for (trajectory, text) in radios {
+            if ui.radio_value(&mut self.trajectory, trajectory, text).changed() {
+                if let Some(chain) = &mut self.morph_chain {
+                    chain.set_trajectory(self.trajectory);
+                    self.draw_object();
+                }
+            }
+        }
+    }
+
+    fn local_morph_nested_menus(&mut self, ui: &mut Ui) {
+        ui.set_max_width(250.0); // To make sure we wrap long text
+
+        let mask = &mut self.ratio_mask;
+        let old_mask = *mask;
+        let is_whole = matches!(mask.region, Region::Whole);
+        let is_sphere = matches!(mask.region, Region::Sphere { .. });
+        let is_half_space = matches!(mask.region, Region::HalfSpace { .. });
+        if ui.radio(is_whole, "Вся модель").clicked() {
+            mask.region = Region::Whole;
+        }
+        if ui.radio(is_sphere, "Шар").clicked() && !is_sphere {
+            mask.region = Region::Sphere {
+                center: Vertex::default(),
+                radius: 0.5,
+            };
+        }
+        if ui.radio(is_half_space, "Полупространство").clicked() && !is_half_space {
+            mask.region = Region::HalfSpace {
+                point: Vertex::default(),
+                normal: Vertex::new(1., 0., 0.),
+            };
+        }
+        ui.label("Координаты от центра модели, с которой начинается морфинг");
+        match &mut mask.region {
+            Region::Whole => (),
+            Region::Sphere { center, radius } => {
+                ui.add(Slider::new(&mut center.x, -2.0..=2.0).text("x"));
+                ui.add(Slider::new(&mut center.y, -2.0..=2.0).text("y"));
+                ui.add(Slider::new(&mut center.z, -2.0..=2.0).text("z"));
+                ui.add(Slider::new(radius, 0.0..=3.0).text("радиус"));
+            }
+            Region::HalfSpace { point, normal } => {
+                ui.add(Slider::new(&mut point.x, -2.0..=2.0).text("x"));
+                ui.add(Slider::new(&mut point.y, -2.0..=2.0).text("y"));
+                ui.add(Slider::new(&mut point.z, -2.0..=2.0).text("z"));
+                ui.add(Slider::new(&mut normal.x, -1.0..=1.0).text("нормаль x"));
+                ui.add(Slider::new(&mut normal.y, -1.0..=1.0).text("нормаль y"));
+                ui.add(Slider::new(&mut normal.z, -1.0..=1.0).text("нормаль z"));
+            }
+        }
+        ui.add(Slider::new(&mut mask.falloff, 0.0..=3.0).text("ширина края"));
+        ui.checkbox(&mut mask.is_painted, "Учитывать нарисованную маску");
+
+        ui.separator();
+        let is_delay = matches!(mask.timing, Timing::Delay(_));
+        if ui.radio(!is_delay, "Вне области вершины стоят на месте").clicked() {
+            mask.timing = Timing::Limit;
+        }
+        if ui.radio(is_delay, "Волна: вне области вершины начинают позже").clicked() && !is_delay {
+            mask.timing = Timing::Delay(0.5);
+        }
+        if let Timing::Delay(delay) = &mut mask.timing {
+            ui.add(Slider::new(delay, 0.0..=1.0).text("задержка"));
+        }
+        if *mask != old_mask {
+            if let Some(chain) = &mut self.morph_chain {
+                chain.set_mask(self.ratio_mask);
+                self.draw_object();
+            }
+        }
+
+        ui.separator();
+        ui.checkbox(&mut self.is_mask_painting, "Рисовать маску (Alt + ЛКМ)");
+        ui.add(Slider::new(&mut self.brush_radius, 2.0..=100.0).text("радиус кисти"));
+        ui.add(Slider::new(&mut self.brush_weight, 0.0..=1.0).text("вес кисти (0 стирает)"));
+        ui.label("Маска рисуется на обозреваемой модели и учитывается при запуске морфинга");
+        if ui.button("Очистить маску").clicked() {
+            let object = match self.mode {
+                Mode::StartObjView => &mut self.start_obj,
+                Mode::ResultObjView => &mut self.result_obj,
+                _ => &mut None,
+            };
+            if let Some(object) = object {
+                object.clear_mask();
+            }
+        }
+    }
+
+    fn playback_nested_menus(&mut self, ui: &mut Ui) {
+        ui.set_max_width(250.0); // To make sure we wrap long text
+
+        ui.horizontal(|ui| {
+            ui.label("Длительность:");
+            ui.add(
+                DragValue::new(&mut self.playback.duration)
+                    .speed(0.1)
+                    .range(0.1..=60.)
+                    .suffix(" с"),
+            );
+        });
+        ui.separator();
+        ui.radio_value(&mut self.playback.repeat, Repeat::Once, "Один раз");
+        ui.radio_value(&mut self.playback.repeat, Repeat::Loop, "По кругу");
+        ui.radio_value(&mut self.playback.repeat, Repeat::PingPong, "Туда и обратно");
+        ui.separator();
+        let easing = &mut self.playback.easing;
+        let radios = [
+            (EasingKind::Linear, "Равномерно"),
+            (EasingKind::EaseIn, "Разгон"),
+            (EasingKind::EaseOut, "Торможение"),
+            (EasingKind::EaseInOut, "Разгон и торможение"),
+            (EasingKind::Bezier, "Кривая Безье"),
+            (EasingKind::Keyframes, "Ключевые кадры"),
+        ];
+        for (kind, text) in radios {
+            ui.radio_value(&mut easing.kind, kind, text);
+        }
+        match easing.kind {
+            EasingKind::Bezier => {
+                let [x1, y1, x2, y2] = &mut easing.bezier;
+                ui.add(Slider::new(x1, 0.0..=1.0).text("x1"));
+                ui.add(Slider::new(y1, -1.0..=2.0).text("y1"));
+                ui.add(Slider::new(x2, 0.0..=1.0).text("x2"));
+                ui.add(Slider::new(y2, -1.0..=2.0).text("y2"));
+            }
+            EasingKind::Keyframes => {
+                let mut removed = None;
+                for (i, (time, ratio)) in easing.keyframes.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(Slider::new(time, 0.0..=1.0).text("время"));
+                        ui.add(Slider::new(ratio, 0.0..=1.0).text("доля"));
+                        if ui.button("Удалить").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = removed {
+                    easing.keyframes.remove(i);
+                }
+                if ui.button("Добавить ключевой кадр").clicked() {
+                    easing.keyframes.push((0.5, 0.5));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn projection_center_nested_menus(&mut self, ui: &mut Ui) {
+        ui.set_max_width(200.0); // To make sure we wrap long text
+
+        let object = match self.mode {
+            Mode::StartObjView => &mut self.start_obj,
+            Mode::ResultObjView => &mut self.result_obj,
+            _ => &mut None,
+        };
+        if ui.button("Среднее вершин").clicked() {
+            if let Some(object) = object {
+                object.set_projection_center(None);
+            }
+        } else if ui
+            .add_enabled(self.center_task.is_none(), Button::new("Центр Чебышёва ядра"))
+            .clicked()
+        {
+            if let Some(object) = object {
+                let is_start = matches!(self.mode, Mode::StartObjView);
+                let task = CenterTask::spawn_center(Kernel::new(object), *object.center());
+                self.center_task = Some((is_start, task));
+            }
+        }
+        ui.checkbox(&mut self.is_center_drag, "Перетаскивать центр (Ctrl + ЛКМ)");
+    }
+
+    fn landmarks_nested_menus(&mut self, ui: &mut Ui) {
+        ui.set_max_width(250.0); // To make sure we wrap long text
+
+        let count = |object: &Option<Object>| object.as_ref().map_or(0, |obj| obj.landmarks().len());
+        ui.label(format!(
+            "Стартовая модель: {}, итоговая модель: {}",
+            count(&self.start_obj),
+            count(&self.result_obj)
+        ));
+        ui.label("Shift + ЛКМ по вершине добавляет точку, точки с одинаковыми номерами совмещаются");
+
+        let object = match self.mode {
+            Mode::StartObjView => &mut self.start_obj,
+            Mode::ResultObjView => &mut self.result_obj,
+            _ => &mut None,
+        };
+        if ui.button("Убрать последнюю точку").clicked() {
+            if let Some(object) = object {
+                object.pop_landmark();
+            }
+        }
+        if ui.button("Очистить точки").clicked() {
+            for object in [&mut self.start_obj, &mut self.result_obj].into_iter().flatten() {
+                object.clear_landmarks();
+            }
+        }
+    }
+
     fn pick_color(&mut self, ui: &mut Ui) {
         color_picker::color_picker_color32(ui, &mut self.obj_color, Alpha::Opaque);
     }
@@ -61,6 +430,7 @@ impl Painting {
             match self.mode {
                 Mode::StartObjView => (),
                 _ => {
+                    self.close_morph();
                     self.mode = Mode::StartObjView;
                     self.draw_object();
                 }
@@ -70,6 +440,7 @@ impl Painting {
             match self.mode {
                 Mode::ResultObjView => (),
                 _ => {
+                    self.close_morph();
                     self.mode = Mode::ResultObjView;
                     self.draw_object();
                 }
@@ -86,10 +457,23 @@ impl Painting {
                     filename.to_str().unwrap_or(""),
                     Color::new(self.obj_color.to_array()),
                 );
-                if loading.is_ok() {
-                    self.start_obj = Some(loading.unwrap());
-                    self.mode = Mode::StartObjView;
-                    self.draw_object();
+                match loading {
+                    Ok(mut object) => {
+                        if self.is_recompute_normals {
+                            object.compute_normals(self.normals_crease());
+                        }
+                        self.start_obj = Some(object);
+                        self.close_morph();
+                        self.mode = Mode::StartObjView;
+                        self.draw_object();
+                    }
+                    Err(e) => {
+                        self.toasts
+                            .error(format!("Ошибка загрузки модели: {e}"))
+                            .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                            .closable(true)
+                            .show_progress_bar(true);
+                    }
                 }
             }
         }
@@ -99,15 +483,56 @@ impl Painting {
                     filename.to_str().unwrap_or(""),
                     Color::new(self.obj_color.to_array()),
                 );
-                if loading.is_ok() {
-                    self.result_obj = Some(loading.unwrap());
-                    self.mode = Mode::ResultObjView;
-                    self.draw_object();
+                match loading {
+                    Ok(mut object) => {
+                        if self.is_recompute_normals {
+                            object.compute_normals(self.normals_crease());
+                        }
+                        self.result_obj = Some(object);
+                        self.close_morph();
+                        self.mode = Mode::ResultObjView;
+                        self.draw_object();
+                    }
+                    Err(e) => {
+                        self.toasts
+                            .error(format!("Ошибка загрузки модели: {e}"))
+                            .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                            .closable(true)
+                            .show_progress_bar(true);
+                    }
                 }
             }
         }
+        let mut is_changed = ui
+            .checkbox(&mut self.is_recompute_normals, "Пересчитывать нормали")
+            .changed();
+        ui.add_enabled_ui(self.is_recompute_normals, |ui| {
+            ui.horizontal(|ui| {
+                is_changed |= ui.checkbox(&mut self.is_crease, "Острые рёбра от").changed();
+                is_changed |= ui
+                    .add_enabled(
+                        self.is_crease,
+                        DragValue::new(&mut self.crease_angle)
+                            .speed(1.)
+                            .range(1.0..=180.)
+                            .suffix("°"),
+                    )
+                    .changed();
+            });
+        });
+        if is_changed && self.is_recompute_normals {
+            let crease_angle = self.normals_crease();
+            for object in [&mut self.start_obj, &mut self.result_obj]
+                .into_iter()
+                .flatten()
+            {
+                object.compute_normals(crease_angle);
+            }
+            self.draw_object();
+        }
         if ui.button("Поменять объекты местами").clicked() {
             swap(&mut self.start_obj, &mut self.result_obj);
+            self.close_morph();
             match self.mode {
                 Mode::StartObjView => self.mode = Mode::ResultObjView,
                 Mode::ResultObjView => self.mode = Mode::StartObjView,
@@ -1,35 +1,70 @@
+mod blend;
 mod events;
 mod labels;
 mod menus;
 mod ops;
+mod playlist;
+mod task;
 
 use crate::canvas::Canvas;
 use crate::color::Color;
 use crate::egui::{
-    CentralPanel, Color32, ColorImage, Context, Event, Mesh, Pos2, Rect, Response, Sense, Shape, Ui,
+    Align2, CentralPanel, Color32, ColorImage, Context, Event, FontId, Mesh, Painter, Pos2, Rect,
+    Response, Sense, Shape, Stroke, Ui, Vec2,
 };
-use crate::figure::merged_object::MergedObject;
+use crate::figure::blend_shape::BlendShape;
+use crate::figure::morph_chain::MorphChain;
 use crate::figure::object::Object;
+use crate::figure::projection::Mapping;
+use crate::figure::ratio_mask::{RatioMask, Region};
+use crate::figure::trajectory::Trajectory;
 use crate::figure::vertex::Vertex;
-use crate::{BACKGROUND_COLOR, EPS, RATIO_STEP, WINDOW_SIZE};
+use crate::playback::Playback;
+use crate::{
+    BACKGROUND_COLOR, DEFAULT_BRUSH_RADIUS, DEFAULT_CREASE_ANGLE, DEFAULT_DURATION, DEFAULT_SCALE,
+    WINDOW_SIZE,
+};
 use egui_notify::Toasts;
+use task::{BlendTask, CenterTask, ChainTask};
 
 enum Mode {
     StartObjView,
     ResultObjView,
     Morphing,
+    Blending,
 }
 pub struct Painting {
     mode: Mode,
     start_obj: Option<Object>,
     result_obj: Option<Object>,
-    merged_obj: Option<MergedObject>,
+    morph_chain: Option<MorphChain>,
+    morph_task: Option<ChainTask>,
+    blend_task: Option<BlendTask>,
+    center_task: Option<(bool, CenterTask)>, // for the start model if the flag is set
+    mapping: Mapping,
+    is_auto_align: bool,
+    trajectory: Trajectory,
+    ratio_mask: RatioMask,
     ratio: f64,
-    step: f64,
+    playback: Playback,
+    playlist: Vec<(String, Object)>, // models of a morph chain with their names
+    is_playlist_open: bool,
+    blend_shape: Option<BlendShape>,
+    blend_targets: Vec<(String, Object, f64)>, // models mixed into the start one with their weights
+    is_blend_open: bool,
     canvas: Canvas,
+    canvas_rect: Rect,
     obj_color: Color32,
     is_movement_access: bool,
     is_rotating_access: bool,
+    is_center_drag: bool,
+    is_mask_painting: bool,
+    is_painting_access: bool,
+    brush_radius: f32,
+    brush_weight: f64,
+    is_recompute_normals: bool,
+    is_crease: bool,
+    crease_angle: f64, // in degrees
     light_direction: Vertex,
     toasts: Toasts,
 }
@@ -39,26 +74,68 @@ impl Default for Painting {
         let mode = Mode::StartObjView;
         let start_obj = None;
         let result_obj = None;
-        let merged_obj = None;
+        let morph_chain = None;
+        let morph_task = None;
+        let blend_task = None;
+        let center_task = None;
+        let mapping = Mapping::default();
+        let is_auto_align = false;
+        let trajectory = Trajectory::default();
+        let ratio_mask = RatioMask::default();
         let ratio = 0.;
-        let step = RATIO_STEP;
+        let playback = Playback::new(DEFAULT_DURATION);
+        let playlist = Vec::new();
+        let is_playlist_open = false;
+        let blend_shape = None;
+        let blend_targets = Vec::new();
+        let is_blend_open = false;
         let canvas = Canvas::new(WINDOW_SIZE.0, WINDOW_SIZE.1, Color::new(BACKGROUND_COLOR));
+        let canvas_rect = Rect::NOTHING;
         let obj_color = Color32::WHITE;
         let is_movement_access = false;
         let is_rotating_access = false;
+        let is_center_drag = false;
+        let is_mask_painting = false;
+        let is_painting_access = false;
+        let brush_radius = DEFAULT_BRUSH_RADIUS;
+        let brush_weight = 1.;
+        let is_recompute_normals = false;
+        let is_crease = false;
+        let crease_angle = DEFAULT_CREASE_ANGLE;
         let light_direction = Vertex::new(0., 0., 1.);
         let toasts = Toasts::default();
         Self {
             mode,
             start_obj,
             result_obj,
-            merged_obj,
+            morph_chain,
+            morph_task,
+            blend_task,
+            center_task,
+            mapping,
+            is_auto_align,
+            trajectory,
+            ratio_mask,
             ratio,
-            step,
+            playback,
+            playlist,
+            is_playlist_open,
+            blend_shape,
+            blend_targets,
+            is_blend_open,
             canvas,
+            canvas_rect,
             obj_color,
             is_movement_access,
             is_rotating_access,
+            is_center_drag,
+            is_mask_painting,
+            is_painting_access,
+            brush_radius,
+            brush_weight,
+            is_recompute_normals,
+            is_crease,
+            crease_angle,
             light_direction,
             toasts,
         }
@@ -68,21 +145,30 @@ impl Default for Painting {
 impl eframe::App for Painting {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         CentralPanel::default().show(ctx, |ui| {
+            self.poll_morph_task();
+            self.poll_blend_task();
+            self.poll_center_task();
             self.ui_menus(ui);
+            self.ui_morph_progress(ui);
+            self.ui_playback(ui);
             self.ui_canvas(ui);
-            if let Mode::Morphing = self.mode {
+            if self.morph_chain.is_some() && self.playback.is_playing() {
+                ctx.request_repaint();
+            }
+            if self.is_task_running() || self.center_task.is_some() {
                 ctx.request_repaint();
             }
             ui.input(|i| {
                 for event in &i.raw.events {
                     match event {
                         Event::MouseMoved(pos) => self.mouse_moved(pos),
+                        Event::PointerMoved(pos) => self.pointer_moved(pos),
                         Event::PointerButton {
+                            pos,
                             button,
                             pressed,
                             modifiers,
-                            ..
-                        } => self.pointer_button(*button, *pressed, *modifiers),
+                        } => self.pointer_button(pos, *button, *pressed, *modifiers),
                         Event::MouseWheel {
                             modifiers, delta, ..
                         } => self.mouse_wheel(*modifiers, delta),
@@ -91,6 +177,8 @@ impl eframe::App for Painting {
                 }
             });
         });
+        self.ui_playlist(ctx);
+        self.ui_blend(ctx);
         self.toasts.show(ctx);
     }
 }
@@ -100,30 +188,13 @@ impl Painting {
         let (response, painter) =
             ui.allocate_painter(ui.available_size_before_wrap(), Sense::drag());
 
-        if self.merged_obj.is_some() {
-            if self.ratio <= 0. {      
-                if self.step < EPS {
-                    self.merged_obj = None;
-                    self.mode = Mode::ResultObjView;
-                } else {
-                    self.mode = Mode::StartObjView;
-                }
+        if self.morph_chain.is_some() {
+            self.playback.advance(ui.input(|i| i.stable_dt) as f64);
+            let ratio = self.playback.ratio();
+            if ratio != self.ratio {
+                self.ratio = ratio;
                 self.draw_object();
-            } else if self.ratio >= 1. {
-                self.mode = Mode::ResultObjView;
-                self.draw_object();
-                if self.step > EPS {
-                    self.merged_obj = None;
-                }
-            } else {
-                self.mode = Mode::Morphing;
-                self.canvas.clear();
-                self.canvas.draw_object(
-                    &self.merged_obj.as_ref().unwrap().interpolation(self.ratio),
-                    self.light_direction,
-                );
             }
-            self.ratio += self.step;
         }
 
         let image = ColorImage::from_rgba_unmultiplied(
@@ -142,7 +213,154 @@ impl Painting {
             Color32::WHITE,
         );
         painter.add(Shape::mesh(mesh));
+        self.canvas_rect = painter.clip_rect();
+        self.draw_center_gizmo(&painter);
+        self.draw_mask(&painter);
+        self.draw_region_gizmo(&painter);
+        self.draw_landmarks(&painter);
 
         response
     }
+
+    // Cross at the projection center of the viewed object
+    fn draw_center_gizmo(&self, painter: &Painter) {
+        let object = match self.mode {
+            Mode::StartObjView => &self.start_obj,
+            Mode::ResultObjView => &self.result_obj,
+            _ => &None,
+        };
+        let Some(object) = object else {
+            return;
+        };
+        if !self.is_center_drag && !object.has_custom_projection_center() {
+            return;
+        }
+
+        let pos = self.world_to_painter(object.projection_center());
+        let stroke = Stroke::new(2., Color32::RED);
+        painter.line_segment([pos - Vec2::X * 8., pos + Vec2::X * 8.], stroke);
+        painter.line_segment([pos - Vec2::Y * 8., pos + Vec2::Y * 8.], stroke);
+        painter.circle_stroke(pos, 4., stroke);
+    }
+
+    // Painted weights of the viewed object, from dark to bright green
+    fn draw_mask(&self, painter: &Painter) {
+        if !self.is_mask_painting {
+            return;
+        }
+        let object = match self.mode {
+            Mode::StartObjView => &self.start_obj,
+            Mode::ResultObjView => &self.result_obj,
+            _ => &None,
+        };
+        let Some(object) = object else {
+            return;
+        };
+
+        for v in 0..object.nvertexes() {
+            let green = (55. + 200. * object.mask_weight(v)) as u8;
+            painter.circle_filled(
+                self.world_to_painter(object.vertex(v)),
+                2.,
+                Color32::from_rgb(0, green, 0),
+            );
+        }
+    }
+
+    // Region of the local morph around the viewed object, the outer circle
+    // or line is where the falloff ends
+    fn draw_region_gizmo(&self, painter: &Painter) {
+        let object = match self.mode {
+            Mode::StartObjView => &self.start_obj,
+            Mode::ResultObjView => &self.result_obj,
+            _ => &None,
+        };
+        let Some(object) = object else {
+            return;
+        };
+
+        let stroke = Stroke::new(2., Color32::LIGHT_BLUE);
+        let falloff_stroke = Stroke::new(1., Color32::LIGHT_BLUE);
+        let falloff = self.ratio_mask.falloff;
+        match self.ratio_mask.region {
+            Region::Whole => (),
+            Region::Sphere { center, radius } => {
+                let center = *object.center() + center;
+                let pos = self.world_to_painter(center);
+                let screen_len = |len: f64| {
+                    self.world_to_painter(center + Vertex::new(len, 0., 0.))
+                        .distance(pos)
+                };
+                painter.circle_stroke(pos, screen_len(radius), stroke);
+                painter.circle_stroke(pos, screen_len(radius + falloff), falloff_stroke);
+            }
+            Region::HalfSpace { point, normal } => {
+                let len = normal.len();
+                if len == 0. {
+                    return;
+                }
+                let normal = normal / len;
+                let point = *object.center() + point;
+                // the boundary plane seen edge-on along the screen direction across the normal
+                let across = Vertex::new(-normal.y, normal.x, 0.);
+                let across = if across.len() > 0. {
+                    across / across.len() * DEFAULT_SCALE
+                } else {
+                    Vertex::new(DEFAULT_SCALE, 0., 0.)
+                };
+                for (shift, stroke) in [(0., stroke), (-falloff, falloff_stroke)] {
+                    let base = point + normal * shift;
+                    painter.line_segment(
+                        [
+                            self.world_to_painter(base - across),
+                            self.world_to_painter(base + across),
+                        ],
+                        stroke,
+                    );
+                }
+                painter.arrow(
+                    self.world_to_painter(point),
+                    self.world_to_painter(point + normal * 0.5) - self.world_to_painter(point),
+                    stroke,
+                );
+            }
+        }
+    }
+
+    // Numbered points picked on the viewed object to match the other one
+    fn draw_landmarks(&self, painter: &Painter) {
+        let object = match self.mode {
+            Mode::StartObjView => &self.start_obj,
+            Mode::ResultObjView => &self.result_obj,
+            _ => &None,
+        };
+        let Some(object) = object else {
+            return;
+        };
+
+        for (i, &v) in object.landmarks().iter().enumerate() {
+            let pos = self.world_to_painter(object.vertex(v));
+            painter.circle_filled(pos, 4., Color32::YELLOW);
+            painter.text(
+                pos + Vec2::new(6., -6.),
+                Align2::LEFT_BOTTOM,
+                (i + 1).to_string(),
+                FontId::proportional(14.),
+                Color32::YELLOW,
+            );
+        }
+    }
+
+    // Position of a point of the scene on the painted canvas
+    fn world_to_painter(&self, v: Vertex) -> Pos2 {
+        let (width, height) = (self.canvas.width(), self.canvas.height());
+        let v = v.world_to_screen(height, width);
+        let rect = self.canvas_rect;
+
+        rect.min
+            + Vec2::new(
+                v.x as f32 / width as f32 * rect.width(),
+                v.y as f32 / height as f32 * rect.height(),
+            )
+    }
 }
@@ -1,16 +1,21 @@
+use super::task::ChainTask;
 use super::{Mode, Painting};
 
-use crate::egui::Ui;
-use crate::figure::merged_object::MergedObject;
-use crate::figure::projection::Projection;
+use crate::color::Color;
+use crate::egui::{Button, Ui};
+use crate::figure::morph_chain::ChainError;
+use crate::figure::object::Object;
+use crate::figure::star_check::StarReport;
 use crate::figure::vertex::Vertex;
-use crate::{DEFAULT_NOTIFY_DURATION, DEFAULT_SCALE, RATIO_STEP, SPHERE_RADIUS};
-use eframe::egui::Vec2;
+use crate::{DEFAULT_NOTIFY_DURATION, DEFAULT_SCALE, PICK_RADIUS};
+use eframe::egui::{Pos2, Vec2};
+use rfd::{FileDialog, MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
 use std::time::Duration;
 
 impl Painting {
     pub fn morph(&mut self, ui: &mut Ui) {
-        if ui.button("Запустить").clicked() {
+        let is_running = self.is_task_running();
+        if ui.add_enabled(!is_running, Button::new("Запустить")).clicked() {
             if self.start_obj.is_none() || self.result_obj.is_none() {
                 self.toasts
                     .info("Для морфинга нужны 2 модели")
@@ -20,29 +25,147 @@ impl Painting {
                 return;
             }
 
-            let is_swap = self.start_obj.as_ref().unwrap().nvertexes() < self.result_obj.as_ref().unwrap().nvertexes();
-            let start_proj = if is_swap {
-                Projection::new(self.result_obj.clone().unwrap(), SPHERE_RADIUS)
-            } else {
-                Projection::new(self.start_obj.clone().unwrap(), SPHERE_RADIUS)
+            let start_landmarks = self.start_obj.as_ref().unwrap().landmarks();
+            let result_landmarks = self.result_obj.as_ref().unwrap().landmarks();
+            if start_landmarks.len() != result_landmarks.len() {
+                self.toasts
+                    .warning("Число точек соответствия на моделях различается, лишние точки не учитываются")
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+
+            let models = vec![
+                ("Стартовая модель".to_string(), self.start_obj.clone().unwrap()),
+                ("Итоговая модель".to_string(), self.result_obj.clone().unwrap()),
+            ];
+            self.morph_task = Some(ChainTask::spawn(models, self.mapping, self.is_auto_align));
+        }
+    }
+
+    pub fn check_models(&mut self, ui: &mut Ui) {
+        if ui.button("Проверить модели").clicked() {
+            if self.start_obj.is_none() && self.result_obj.is_none() {
+                self.toasts
+                    .info("Нет загруженных моделей")
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+                return;
+            }
+            self.check_star_shaped();
+        }
+    }
+
+    // Reports every loaded model, the morph runs the same check on its worker
+    fn check_star_shaped(&mut self) {
+        let objects = [
+            ("Стартовая модель", &self.start_obj),
+            ("Итоговая модель", &self.result_obj),
+        ];
+        for (name, obj) in objects {
+            let Some(obj) = obj else {
+                continue;
             };
-            let result_proj = if is_swap {
-                Projection::new(self.start_obj.clone().unwrap(), SPHERE_RADIUS)
+            let report = StarReport::new(obj, obj.projection_center());
+            if !report.is_star_shaped() {
+                self.toasts
+                    .warning(format!("{name}: {report}"))
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
             } else {
-                Projection::new(self.result_obj.clone().unwrap(), SPHERE_RADIUS)
-            };
-            if let Ok(obj) = MergedObject::new(start_proj, result_proj) {
-                self.merged_obj = Some(obj);
-                if is_swap {
-                    self.step = -RATIO_STEP;
-                    self.ratio = 1.;
-                } else {
-                    self.step = RATIO_STEP;
-                    self.ratio = 0.;
+                self.toasts
+                    .info(format!("{name}: {report}"))
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+        }
+    }
+
+    pub fn poll_morph_task(&mut self) {
+        if !self.morph_task.as_ref().is_some_and(|task| task.is_finished()) {
+            return;
+        }
+        let task = self.morph_task.take().unwrap();
+        let is_cancelled = task.is_cancelled();
+        let (link, nlinks) = (task.link(), task.nlinks());
+        // the morph is still attempted, a model may work despite the warnings
+        if !is_cancelled {
+            for warning in task.take_warnings() {
+                self.toasts
+                    .warning(warning)
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+        }
+        let result = task.join().unwrap_or_else(|error| {
+            Err(ChainError {
+                link,
+                nlinks,
+                error,
+            })
+        });
+        match result {
+            // the result may still arrive if the worker was past its last check
+            _ if is_cancelled => {
+                self.toasts
+                    .info("Морфинг отменён")
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+            Ok(mut chain) => {
+                chain.set_trajectory(self.trajectory);
+                chain.set_mask(self.ratio_mask);
+                self.morph_chain = Some(chain);
+                self.mode = Mode::Morphing;
+                self.ratio = 0.;
+                self.playback.play_from_start();
+                self.draw_object();
+            }
+            Err(e) => {
+                self.toasts
+                    .error(format!("Морфинг невозможен: {e}"))
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+        }
+    }
+
+    pub fn poll_center_task(&mut self) {
+        if !self
+            .center_task
+            .as_ref()
+            .is_some_and(|(_, task)| task.is_finished())
+        {
+            return;
+        }
+        let (is_start, task) = self.center_task.take().unwrap();
+        let object = if is_start {
+            &mut self.start_obj
+        } else {
+            &mut self.result_obj
+        };
+        match task.join() {
+            Ok(Some((center, _))) => {
+                if let Some(object) = object {
+                    object.set_projection_center(Some(center));
                 }
-            } else {
+            }
+            Ok(None) => {
+                self.toasts
+                    .warning("Ядро модели пусто: модель не звёздная")
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+            Err(e) => {
                 self.toasts
-                    .info("Некорректная модель")
+                    .error(format!("Центр ядра не найден: {e}"))
                     .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
                     .closable(true)
                     .show_progress_bar(true);
@@ -50,6 +173,84 @@ impl Painting {
         }
     }
 
+    pub fn save_current_obj(&mut self, ui: &mut Ui) {
+        if ui.button("Сохранить кадр в OBJ...").clicked() {
+            let Some(object) = self.current_object() else {
+                self.toasts
+                    .info("Нет объекта для сохранения")
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+                return;
+            };
+            let Some(path) = FileDialog::new().add_filter("obj", &["obj"]).save_file() else {
+                return;
+            };
+            if path.file_stem().is_none() {
+                self.toasts
+                    .error(format!("Некорректный путь: {}", path.display()))
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+                return;
+            }
+            // the dialog only asks about the .obj, the material library next to it is confirmed here
+            let mtl_path = path.with_extension("mtl");
+            if mtl_path.exists()
+                && MessageDialog::new()
+                    .set_level(MessageLevel::Warning)
+                    .set_title("Сохранить кадр в OBJ")
+                    .set_description(format!(
+                        "Файл материалов {} уже существует. Перезаписать его?",
+                        mtl_path.display()
+                    ))
+                    .set_buttons(MessageButtons::YesNo)
+                    .show()
+                    != MessageDialogResult::Yes
+            {
+                return;
+            }
+            if let Err(e) = object.save(&path, true) {
+                self.toasts
+                    .error(format!("Не удалось сохранить объект: {e}"))
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+            }
+        }
+    }
+
+    // The morph of two models as one mesh which plays in glTF viewers
+    pub fn save_morph_gltf(&mut self, ui: &mut Ui) {
+        if ui.button("Сохранить морфинг в glTF...").clicked() {
+            let Some(chain) = &self.morph_chain else {
+                self.toasts
+                    .info("Сначала запустите морфинг")
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+                return;
+            };
+            let Some(mesh) = chain.morph_mesh(&self.playback.keyframes()) else {
+                self.toasts
+                    .info("В glTF сохраняется только морфинг двух моделей")
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+                return;
+            };
+            if let Some(path) = FileDialog::new().add_filter("glTF", &["glb"]).save_file() {
+                if let Err(e) = mesh.save(&path) {
+                    self.toasts
+                        .error(format!("Не удалось сохранить морфинг: {e}"))
+                        .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                        .closable(true)
+                        .show_progress_bar(true);
+                }
+            }
+        }
+    }
+
     pub fn move_object(&mut self, delta: &Vec2) {
         let delta = Vertex::new(
             delta.x as f64 / self.canvas.width() as f64 * DEFAULT_SCALE,
@@ -67,6 +268,79 @@ impl Painting {
         }
     }
 
+    pub fn move_projection_center(&mut self, delta: &Vec2) {
+        let delta = Vertex::new(
+            delta.x as f64 / self.canvas.width() as f64 * DEFAULT_SCALE,
+            delta.y as f64 / self.canvas.height() as f64 * DEFAULT_SCALE,
+            0.,
+        );
+        let object = match self.mode {
+            Mode::StartObjView => &mut self.start_obj,
+            Mode::ResultObjView => &mut self.result_obj,
+            _ => &mut None,
+        };
+        if let Some(object) = object {
+            object.set_projection_center(Some(object.projection_center() + delta));
+        }
+    }
+
+    // Marks the visible vertex under the cursor as the next point to match
+    pub fn pick_landmark(&mut self, pos: &Pos2) {
+        if !self.canvas_rect.contains(*pos) {
+            return;
+        }
+        let object = match self.mode {
+            Mode::StartObjView => &self.start_obj,
+            Mode::ResultObjView => &self.result_obj,
+            _ => &None,
+        };
+        let Some(object) = object else {
+            return;
+        };
+        // the nearest to the viewer of the vertexes close to the cursor
+        let picked = (0..object.nvertexes())
+            .filter(|&v| self.world_to_painter(object.vertex(v)).distance(*pos) <= PICK_RADIUS)
+            .max_by(|&a, &b| object.vertex(a).z.total_cmp(&object.vertex(b).z));
+
+        let object = match self.mode {
+            Mode::StartObjView => &mut self.start_obj,
+            Mode::ResultObjView => &mut self.result_obj,
+            _ => &mut None,
+        };
+        if let (Some(object), Some(v)) = (object, picked) {
+            object.add_landmark(v);
+        }
+    }
+
+    // Sets the brush weight to the vertexes under the cursor, on both sides of the model
+    pub fn paint_mask(&mut self, pos: &Pos2) {
+        if !self.canvas_rect.contains(*pos) {
+            return;
+        }
+        let object = match self.mode {
+            Mode::StartObjView => &self.start_obj,
+            Mode::ResultObjView => &self.result_obj,
+            _ => &None,
+        };
+        let Some(object) = object else {
+            return;
+        };
+        let painted = (0..object.nvertexes())
+            .filter(|&v| self.world_to_painter(object.vertex(v)).distance(*pos) <= self.brush_radius)
+            .collect::<Vec<_>>();
+
+        let object = match self.mode {
+            Mode::StartObjView => &mut self.start_obj,
+            Mode::ResultObjView => &mut self.result_obj,
+            _ => &mut None,
+        };
+        if let Some(object) = object {
+            for v in painted {
+                object.paint_mask(v, self.brush_weight);
+            }
+        }
+    }
+
     pub fn rotate_object(&mut self, delta: &Vec2) {
         let delta = Vertex::new(
             -delta.y as f64 / self.canvas.height() as f64,
@@ -97,15 +371,81 @@ impl Painting {
         }
     }
 
+    fn current_object(&self) -> Option<Object> {
+        match self.mode {
+            Mode::StartObjView => self.start_obj.clone(),
+            Mode::ResultObjView => self.result_obj.clone(),
+            Mode::Morphing => self
+                .morph_chain
+                .as_ref()
+                .map(|chain| chain.interpolation(self.ratio)),
+            Mode::Blending => self
+                .blend_shape
+                .as_ref()
+                .map(|shape| shape.interpolation(&self.blend_weights())),
+        }
+    }
+
     pub fn draw_object(&mut self) {
         self.canvas.clear();
         let object = match self.mode {
             Mode::StartObjView => &mut self.start_obj,
             Mode::ResultObjView => &mut self.result_obj,
-            _ => &mut None,
+            Mode::Morphing | Mode::Blending => {
+                if let Some(frame) = self.current_object() {
+                    self.canvas.draw_object(&frame, self.light_direction);
+                }
+                return;
+            }
         };
         if let Some(object) = object {
             self.canvas.draw_object(object, self.light_direction);
         }
     }
+
+    pub fn is_task_running(&self) -> bool {
+        self.morph_task.is_some() || self.blend_task.is_some()
+    }
+
+    // Loads a model chosen in the file dialog, named after its file
+    pub fn pick_model(&mut self) -> Option<(String, Object)> {
+        let filename = FileDialog::new().add_filter("obj", &["obj"]).pick_file()?;
+        let name = filename
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match Object::load(
+            filename.to_str().unwrap_or(""),
+            Color::new(self.obj_color.to_array()),
+        ) {
+            Ok(mut object) => {
+                if self.is_recompute_normals {
+                    object.compute_normals(self.normals_crease());
+                }
+                Some((name, object))
+            }
+            Err(e) => {
+                self.toasts
+                    .error(format!("Ошибка загрузки модели: {e}"))
+                    .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                    .closable(true)
+                    .show_progress_bar(true);
+                None
+            }
+        }
+    }
+
+    // Angle in radians above which recomputed normals keep the edges sharp
+    pub fn normals_crease(&self) -> Option<f64> {
+        self.is_crease.then(|| self.crease_angle.to_radians())
+    }
+
+    // Leaves the played morph for viewing and editing the models
+    pub fn close_morph(&mut self) {
+        self.morph_chain = None;
+        self.playback.pause();
+        if let Mode::Morphing = self.mode {
+            self.mode = Mode::StartObjView;
+        }
+    }
 }
@@ -0,0 +1,101 @@
+use super::task::ChainTask;
+use super::Painting;
+
+use std::time::Duration;
+
+use crate::egui::{Button, Context, Ui, Window};
+use crate::DEFAULT_NOTIFY_DURATION;
+
+impl Painting {
+    // Models morphed one after another, the playback flows through all of them
+    pub fn ui_playlist(&mut self, ctx: &Context) {
+        let mut is_open = self.is_playlist_open;
+        Window::new("Цепочка моделей")
+            .open(&mut is_open)
+            .resizable(false)
+            .show(ctx, |ui| self.playlist_contents(ui));
+        self.is_playlist_open = is_open;
+    }
+
+    fn playlist_contents(&mut self, ui: &mut Ui) {
+        let n = self.playlist.len();
+        if n < 2 {
+            ui.label("Для морфинга нужны хотя бы 2 модели");
+        }
+        let mut moved = None;
+        let mut removed = None;
+        for (i, (name, _)) in self.playlist.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}. {name}", i + 1));
+                if ui.add_enabled(i > 0, Button::new("Вверх")).clicked() {
+                    moved = Some((i, i - 1));
+                }
+                if ui.add_enabled(i + 1 < n, Button::new("Вниз")).clicked() {
+                    moved = Some((i, i + 1));
+                }
+                if ui.button("Удалить").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some((a, b)) = moved {
+            self.playlist.swap(a, b);
+        }
+        if let Some(i) = removed {
+            self.playlist.remove(i);
+        }
+
+        ui.separator();
+        if ui.button("Добавить модель...").clicked() {
+            if let Some(model) = self.pick_model() {
+                self.playlist.push(model);
+            }
+        }
+        ui.horizontal(|ui| {
+            if let Some(obj) = &self.start_obj {
+                if ui.button("Добавить стартовый объект").clicked() {
+                    self.playlist
+                        .push(("стартовый объект".to_string(), obj.clone()));
+                }
+            }
+            if let Some(obj) = &self.result_obj {
+                if ui.button("Добавить итоговый объект").clicked() {
+                    self.playlist
+                        .push(("итоговый объект".to_string(), obj.clone()));
+                }
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let is_ready = n >= 2 && !self.is_task_running();
+            if ui.add_enabled(is_ready, Button::new("Запустить")).clicked() {
+                self.morph_playlist();
+            }
+            if ui.button("Очистить").clicked() {
+                self.playlist.clear();
+            }
+        });
+    }
+
+    // Models which are not star-shaped are reported by the worker, as in the morph of two models
+    fn morph_playlist(&mut self) {
+        let is_landmarks_mismatch = self
+            .playlist
+            .windows(2)
+            .any(|pair| pair[0].1.landmarks().len() != pair[1].1.landmarks().len());
+        if is_landmarks_mismatch {
+            self.toasts
+                .warning("Число точек соответствия на соседних моделях различается, лишние точки не учитываются")
+                .duration(Some(Duration::from_secs(DEFAULT_NOTIFY_DURATION)))
+                .closable(true)
+                .show_progress_bar(true);
+        }
+
+        self.morph_task = Some(ChainTask::spawn(
+            self.playlist.clone(),
+            self.mapping,
+            self.is_auto_align,
+        ));
+    }
+}
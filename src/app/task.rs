@@ -0,0 +1,274 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::figure::alignment::RotationFit;
+use crate::figure::blend_shape::{BlendError, BlendShape};
+use crate::figure::kernel::Kernel;
+use crate::figure::merged_object::MergedObject;
+use crate::figure::morph_chain::{ChainError, MorphChain};
+use crate::figure::morph_error::{Mesh, MorphError};
+use crate::figure::object::Object;
+use crate::figure::progress::{Phase, Progress};
+use crate::figure::projection::{Mapping, Projection};
+use crate::figure::star_check::StarReport;
+use crate::figure::vertex::Vertex;
+use crate::SPHERE_RADIUS;
+
+// Morph prepared on a worker thread, so the window keeps responding
+pub struct MorphTask<T> {
+    progress: Arc<Progress>,
+    link: Arc<AtomicUsize>,
+    nlinks: usize,
+    warnings: Arc<Mutex<Vec<String>>>, // filled by the worker before the morph itself
+    handle: JoinHandle<T>,
+}
+
+pub type ChainTask = MorphTask<Result<MorphChain, ChainError>>;
+pub type BlendTask = MorphTask<Result<BlendShape, BlendError>>;
+pub type CenterTask = MorphTask<Option<(Vertex, f64)>>;
+
+impl ChainTask {
+    // Merges each of the named `models` with the next one, the vertexes picked by the user
+    // are matched, `is_auto_align` turns the target sphere to fit the source model first
+    pub fn spawn(models: Vec<(String, Object)>, mapping: Mapping, is_auto_align: bool) -> Self {
+        let progress = Arc::new(Progress::new());
+        let link = Arc::new(AtomicUsize::new(0));
+        let nlinks = models.len().saturating_sub(1);
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let worker_progress = progress.clone();
+        let worker_link = link.clone();
+        let worker_warnings = warnings.clone();
+        let handle = thread::spawn(move || {
+            let progress = worker_progress;
+            if mapping == Mapping::Central {
+                let named = models
+                    .iter()
+                    .map(|(name, obj)| (name.as_str(), obj))
+                    .collect::<Vec<_>>();
+                *worker_warnings.lock().unwrap() = star_warnings(&named, &progress);
+            }
+            let models = models.into_iter().map(|(_, obj)| obj).collect();
+            MorphChain::new(models, |i, src, dst, pairs| {
+                worker_link.store(i, Ordering::Relaxed);
+                if progress.is_cancelled() {
+                    return Err(MorphError::Cancelled);
+                }
+                progress.start(Phase::Projection, 1);
+                let src_proj = Projection::with_mapping(src, SPHERE_RADIUS, mapping)
+                    .map_err(|kind| Mesh::Source.error(kind))?;
+                let dst_proj =
+                    project_aligned(dst, &src_proj, mapping, &pairs, is_auto_align, &progress)?;
+                let mut merged = MergedObject::with_progress(src_proj, dst_proj, &progress)?;
+                if progress.is_cancelled() {
+                    return Err(MorphError::Cancelled);
+                }
+                progress.start(Phase::Trajectory, 1);
+                merged.build_rigid_path();
+                progress.advance(1);
+                Ok(merged)
+            })
+        });
+
+        Self {
+            progress,
+            link,
+            nlinks,
+            warnings,
+            handle,
+        }
+    }
+}
+
+impl BlendTask {
+    // The vertexes picked on each target are matched to those picked on the base,
+    // the names refer to the models in the warnings
+    pub fn spawn_blend(
+        base: (String, Object),
+        targets: Vec<(String, Object)>,
+        mapping: Mapping,
+        is_auto_align: bool,
+    ) -> Self {
+        let progress = Arc::new(Progress::new());
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let worker_progress = progress.clone();
+        let worker_warnings = warnings.clone();
+        let handle = thread::spawn(move || {
+            let progress = worker_progress;
+            if mapping == Mapping::Central {
+                let named = std::iter::once(&base)
+                    .chain(&targets)
+                    .map(|(name, obj)| (name.as_str(), obj))
+                    .collect::<Vec<_>>();
+                *worker_warnings.lock().unwrap() = star_warnings(&named, &progress);
+            }
+            let (_, base) = base;
+            let in_target = |target, error| BlendError {
+                target: Some(target),
+                error,
+            };
+            let landmarks = base.landmarks().to_vec();
+            progress.start(Phase::Projection, 1);
+            let base_proj =
+                Projection::with_mapping(base, SPHERE_RADIUS, mapping).map_err(|kind| {
+                    BlendError {
+                        target: None,
+                        error: Mesh::Source.error(kind),
+                    }
+                })?;
+            let mut target_projs = Vec::with_capacity(targets.len());
+            for (k, (_, target)) in targets.into_iter().enumerate() {
+                let pairs = landmarks
+                    .iter()
+                    .copied()
+                    .zip(target.landmarks().iter().copied())
+                    .collect::<Vec<_>>();
+                let proj = project_aligned(
+                    target,
+                    &base_proj,
+                    mapping,
+                    &pairs,
+                    is_auto_align,
+                    &progress,
+                )
+                .map_err(|error| in_target(k, error))?;
+                target_projs.push(proj);
+            }
+
+            BlendShape::with_progress(base_proj, target_projs, &progress)
+        });
+
+        Self {
+            progress,
+            link: Arc::new(AtomicUsize::new(0)),
+            nlinks: 1,
+            warnings,
+            handle,
+        }
+    }
+}
+
+impl CenterTask {
+    // Chebyshev center of the kernel, searched for from `start`
+    pub fn spawn_center(kernel: Kernel, start: Vertex) -> Self {
+        Self {
+            progress: Arc::new(Progress::new()),
+            link: Arc::new(AtomicUsize::new(0)),
+            nlinks: 1,
+            warnings: Arc::new(Mutex::new(Vec::new())),
+            handle: thread::spawn(move || kernel.chebyshev_center(start)),
+        }
+    }
+}
+
+impl<T> MorphTask<T> {
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    // Link being merged, from 0
+    pub fn link(&self) -> usize {
+        self.link.load(Ordering::Relaxed)
+    }
+
+    pub fn nlinks(&self) -> usize {
+        self.nlinks
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.progress.is_cancelled()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    // Models which are not star-shaped, the morph is still attempted with them
+    pub fn take_warnings(&self) -> Vec<String> {
+        std::mem::take(&mut self.warnings.lock().unwrap())
+    }
+
+    // A panic of the worker is returned as an error instead of taking the window down
+    pub fn join(self) -> Result<T, MorphError> {
+        self.handle.join().map_err(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            MorphError::Panicked(message)
+        })
+    }
+}
+
+// One line for each of the named models which is not star-shaped about its projection center
+fn star_warnings(models: &[(&str, &Object)], progress: &Progress) -> Vec<String> {
+    progress.start(Phase::StarCheck, models.len());
+    let mut warnings = Vec::new();
+    for &(name, obj) in models {
+        if progress.is_cancelled() {
+            break;
+        }
+        let report = StarReport::new(obj, obj.projection_center());
+        if !report.is_star_shaped() {
+            warnings.push(format!("{name}: {report}"));
+        }
+        progress.advance(1);
+    }
+
+    warnings
+}
+
+// Target model on the sphere turned and warped to the source, errors
+// refer to it as to the target
+fn project_aligned(
+    dst: Object,
+    src_proj: &Projection,
+    mapping: Mapping,
+    pairs: &[(usize, usize)],
+    is_auto_align: bool,
+    progress: &Progress,
+) -> Result<Projection, MorphError> {
+    if progress.is_cancelled() {
+        return Err(MorphError::Cancelled);
+    }
+    progress.start(Phase::Projection, 1);
+    let mut dst_proj = Projection::with_mapping(dst, SPHERE_RADIUS, mapping)
+        .map_err(|kind| Mesh::Target.error(kind))?;
+    progress.advance(1);
+
+    if is_auto_align {
+        progress.start(Phase::Alignment, RotationFit::nsteps());
+        let rotation = RotationFit::new(src_proj, &dst_proj).find(progress);
+        if progress.is_cancelled() {
+            return Err(MorphError::Cancelled);
+        }
+        dst_proj.rotate_sphere(&rotation);
+    }
+    dst_proj
+        .align_to(src_proj, pairs)
+        .map_err(|kind| Mesh::Target.error(kind))?;
+
+    Ok(dst_proj)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panic_is_an_error() {
+        let task = MorphTask::<()> {
+            progress: Arc::new(Progress::new()),
+            link: Arc::new(AtomicUsize::new(0)),
+            nlinks: 1,
+            warnings: Arc::new(Mutex::new(Vec::new())),
+            handle: thread::spawn(|| panic!("пустая модель")),
+        };
+
+        assert_eq!(
+            task.join(),
+            Err(MorphError::Panicked("пустая модель".to_string()))
+        );
+    }
+}
@@ -0,0 +1,442 @@
+use Morphing::figure::alignment::RotationFit;
+use Morphing::figure::kernel::Kernel;
+use Morphing::figure::morph_chain::MorphChain;
+use Morphing::figure::morph_error::Mesh;
+use Morphing::figure::progress::Progress;
+use Morphing::figure::projection::Mapping;
+use Morphing::figure::ratio_mask::{RatioMask, Region, Timing};
+use Morphing::figure::star_check::StarReport;
+use Morphing::figure::trajectory::Trajectory;
+use Morphing::playback::Playback;
+use Morphing::texture::{Filter, Sampler, SharedTexture, Texture, Wrap};
+use Morphing::{
+    Canvas, Color, MergedObject, Object, Projection, Vertex, BACKGROUND_COLOR, DEFAULT_DURATION,
+    SPHERE_RADIUS, WINDOW_SIZE,
+};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "\
+Использование: morph_render <стартовый.obj> [промежуточный.obj ...] <итоговый.obj> [параметры]
+
+Модели морфятся по очереди, каждая в следующую.
+
+Параметры:
+    -n, --frames <N>            количество кадров (по умолчанию 100)
+    -m, --meshes <N>            количество промежуточных моделей в OBJ (по умолчанию 0)
+    -l, --light <X,Y,Z>         направление источника света (по умолчанию 0,0,1)
+    -c, --colors <R,G,B:R,G,B...>
+                                цвета моделей по порядку
+    -s, --size <WxH>            размер кадра (по умолчанию 1000x800)
+    --start-texture <FILE>      текстура стартового объекта
+    --result-texture <FILE>     текстура итогового объекта
+    --filter <nearest|bilinear> фильтрация текстур (по умолчанию bilinear)
+    --wrap <repeat|clamp>       выход за границы текстуры (по умолчанию repeat)
+    --crease <DEG>              пересчитать нормали, не сглаживая рёбра острее угла
+                                в градусах (по умолчанию нормали из файлов)
+    --center <centroid|kernel>  центр проекции: среднее вершин или центр Чебышёва ядра
+                                (по умолчанию centroid)
+    --mapping <central|harmonic>
+                                отображение на сферу: центральная проекция или
+                                гармоническое для не звёздных моделей (по умолчанию central)
+    -p, --pairs <I:J,...>       совмещаемые вершины стартового и итогового объектов
+                                (номера с 1, как в OBJ), только для 2 моделей
+    --auto-align                повернуть сферу итоговой модели к форме стартовой
+    -t, --trajectory <linear|spherical|rigid>
+                                траектории вершин: прямые, сферические или
+                                как можно более жёсткие (по умолчанию linear)
+    -r, --region <sphere:X,Y,Z,R|half:X,Y,Z,NX,NY,NZ>
+                                морфится только шар или полупространство по нормали,
+                                координаты от центра модели (по умолчанию вся модель)
+    --falloff <D>               ширина плавного края области (по умолчанию 0.5)
+    --delay <D>                 вершины вне области тоже морфятся, но начинают позже
+                                на эту долю морфинга, от 0 до 1 (волна)
+    -g, --gltf <FILE>           сохранить морфинг в glTF (GLB) с целью морфинга и
+                                анимацией её веса, только для 2 моделей
+    -d, --duration <S>          длительность анимации в glTF в секундах (по умолчанию 2)
+    -f, --format <png|ppm>      формат кадров (по умолчанию png)
+    -o, --out <DIR>             каталог для кадров (по умолчанию frames)
+    -h, --help                  показать эту справку";
+
+struct Args {
+    models: Vec<String>,
+    frames: usize,
+    meshes: usize,
+    light_direction: Vertex,
+    colors: Vec<Color>,
+    size: (u32, u32),
+    textures: (Option<String>, Option<String>),
+    sampler: Sampler,
+    crease_angle: Option<f64>, // in radians
+    is_kernel_center: bool,
+    mapping: Mapping,
+    pairs: Vec<(usize, usize)>,
+    is_auto_align: bool,
+    trajectory: Trajectory,
+    mask: RatioMask,
+    gltf: Option<PathBuf>,
+    duration: f64,
+    format: String,
+    out: PathBuf,
+}
+
+impl Args {
+    fn parse() -> Result<Option<Self>, String> {
+        let mut args = Self {
+            models: Vec::new(),
+            frames: 100,
+            meshes: 0,
+            light_direction: Vertex::new(0., 0., 1.),
+            colors: Vec::new(),
+            size: WINDOW_SIZE,
+            textures: (None, None),
+            sampler: Sampler::default(),
+            crease_angle: None,
+            is_kernel_center: false,
+            mapping: Mapping::default(),
+            pairs: Vec::new(),
+            is_auto_align: false,
+            trajectory: Trajectory::default(),
+            mask: RatioMask::default(),
+            gltf: None,
+            duration: DEFAULT_DURATION,
+            format: "png".to_string(),
+            out: PathBuf::from("frames"),
+        };
+
+        let mut iter = env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .ok_or_else(|| format!("Не указано значение для {arg}"))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-n" | "--frames" => {
+                    args.frames = value()?
+                        .parse()
+                        .map_err(|_| "Некорректное количество кадров".to_string())?
+                }
+                "-m" | "--meshes" => {
+                    args.meshes = value()?
+                        .parse()
+                        .map_err(|_| "Некорректное количество моделей".to_string())?
+                }
+                "-l" | "--light" => args.light_direction = parse_vertex(&value()?)?,
+                "-c" | "--colors" => args.colors = parse_colors(&value()?)?,
+                "-s" | "--size" => args.size = parse_size(&value()?)?,
+                "--start-texture" => args.textures.0 = Some(value()?),
+                "--result-texture" => args.textures.1 = Some(value()?),
+                "--filter" => {
+                    args.sampler.filter = match value()?.as_str() {
+                        "nearest" => Filter::Nearest,
+                        "bilinear" => Filter::Bilinear,
+                        other => return Err(format!("Неизвестная фильтрация: {other}")),
+                    }
+                }
+                "--wrap" => {
+                    args.sampler.wrap = match value()?.as_str() {
+                        "repeat" => Wrap::Repeat,
+                        "clamp" => Wrap::Clamp,
+                        other => return Err(format!("Неизвестный режим повторения: {other}")),
+                    }
+                }
+                "--crease" => {
+                    let angle = value()?
+                        .parse()
+                        .ok()
+                        .filter(|angle: &f64| *angle > 0. && *angle <= 180.)
+                        .ok_or_else(|| "Некорректный угол острых рёбер".to_string())?;
+                    args.crease_angle = Some(angle.to_radians());
+                }
+                "--center" => {
+                    args.is_kernel_center = match value()?.as_str() {
+                        "centroid" => false,
+                        "kernel" => true,
+                        other => return Err(format!("Неизвестный центр проекции: {other}")),
+                    }
+                }
+                "--mapping" => {
+                    args.mapping = match value()?.as_str() {
+                        "central" => Mapping::Central,
+                        "harmonic" => Mapping::Harmonic,
+                        other => return Err(format!("Неизвестное отображение: {other}")),
+                    }
+                }
+                "-p" | "--pairs" => args.pairs = parse_pairs(&value()?)?,
+                "--auto-align" => args.is_auto_align = true,
+                "-t" | "--trajectory" => {
+                    args.trajectory = match value()?.as_str() {
+                        "linear" => Trajectory::Linear,
+                        "spherical" => Trajectory::Spherical,
+                        "rigid" => Trajectory::Rigid,
+                        other => return Err(format!("Неизвестная траектория: {other}")),
+                    }
+                }
+                "-r" | "--region" => args.mask.region = parse_region(&value()?)?,
+                "--falloff" => {
+                    args.mask.falloff = value()?
+                        .parse()
+                        .ok()
+                        .filter(|falloff: &f64| *falloff >= 0.)
+                        .ok_or_else(|| "Некорректная ширина края области".to_string())?
+                }
+                "--delay" => {
+                    let delay = value()?
+                        .parse()
+                        .ok()
+                        .filter(|delay| (0.0..=1.).contains(delay))
+                        .ok_or_else(|| "Некорректная задержка".to_string())?;
+                    args.mask.timing = Timing::Delay(delay);
+                }
+                "-g" | "--gltf" => args.gltf = Some(PathBuf::from(value()?)),
+                "-d" | "--duration" => {
+                    args.duration = value()?
+                        .parse()
+                        .ok()
+                        .filter(|duration: &f64| *duration > 0.)
+                        .ok_or_else(|| "Некорректная длительность".to_string())?
+                }
+                "-f" | "--format" => {
+                    args.format = value()?;
+                    if args.format != "png" && args.format != "ppm" {
+                        return Err(format!("Неизвестный формат кадров: {}", args.format));
+                    }
+                }
+                "-o" | "--out" => args.out = PathBuf::from(value()?),
+                _ if arg.starts_with('-') => return Err(format!("Неизвестный параметр: {arg}")),
+                _ => args.models.push(arg),
+            }
+        }
+
+        if args.models.len() < 2 {
+            return Err("Для морфинга нужны хотя бы 2 модели".to_string());
+        }
+        if args.colors.is_empty() {
+            args.colors = vec![Color::new([255; 4]); args.models.len()];
+        } else if args.colors.len() != args.models.len() {
+            return Err("Число цветов не совпадает с числом моделей".to_string());
+        }
+        if !args.pairs.is_empty() && args.models.len() != 2 {
+            return Err("Пары вершин задаются только для 2 моделей".to_string());
+        }
+        if args.gltf.is_some() && args.models.len() != 2 {
+            return Err("glTF сохраняется только для 2 моделей".to_string());
+        }
+        if args.frames == 0 && args.meshes == 0 && args.gltf.is_none() {
+            return Err("Нечего сохранять: не заданы ни кадры, ни модели".to_string());
+        }
+        Ok(Some(args))
+    }
+}
+
+fn parse_numbers<T: std::str::FromStr>(s: &str, sep: char, n: usize) -> Option<Vec<T>> {
+    let vals = s
+        .split(sep)
+        .map(|v| v.trim().parse())
+        .collect::<Result<Vec<T>, _>>()
+        .ok()?;
+
+    (vals.len() == n).then_some(vals)
+}
+
+fn parse_vertex(s: &str) -> Result<Vertex, String> {
+    let v = parse_numbers::<f64>(s, ',', 3)
+        .ok_or_else(|| format!("Некорректное направление света: {s}"))?;
+
+    Ok(Vertex::new(v[0], v[1], v[2]))
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let c = parse_numbers::<u8>(s, ',', 3).ok_or_else(|| format!("Некорректный цвет: {s}"))?;
+
+    Ok(Color::new([c[0], c[1], c[2], 255]))
+}
+
+fn parse_colors(s: &str) -> Result<Vec<Color>, String> {
+    s.split(':').map(parse_color).collect()
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let size =
+        parse_numbers::<u32>(s, 'x', 2).ok_or_else(|| format!("Некорректный размер: {s}"))?;
+
+    Ok((size[0], size[1]))
+}
+
+fn parse_region(s: &str) -> Result<Region, String> {
+    let error = || format!("Некорректная область: {s}");
+    let (kind, values) = s.split_once(':').ok_or_else(error)?;
+    match kind {
+        "sphere" => {
+            let v = parse_numbers::<f64>(values, ',', 4).ok_or_else(error)?;
+            Ok(Region::Sphere {
+                center: Vertex::new(v[0], v[1], v[2]),
+                radius: v[3],
+            })
+        }
+        "half" => {
+            let v = parse_numbers::<f64>(values, ',', 6).ok_or_else(error)?;
+            Ok(Region::HalfSpace {
+                point: Vertex::new(v[0], v[1], v[2]),
+                normal: Vertex::new(v[3], v[4], v[5]),
+            })
+        }
+        _ => Err(error()),
+    }
+}
+
+// Indices from 1 as in OBJ are turned into indices from 0
+fn parse_pairs(s: &str) -> Result<Vec<(usize, usize)>, String> {
+    s.split(',')
+        .map(|pair| {
+            parse_numbers::<usize>(pair, ':', 2)
+                .filter(|p| p[0] > 0 && p[1] > 0)
+                .map(|p| (p[0] - 1, p[1] - 1))
+                .ok_or_else(|| format!("Некорректная пара вершин: {pair}"))
+        })
+        .collect()
+}
+
+fn progress(i: usize, n: usize) -> f64 {
+    if n > 1 {
+        i as f64 / (n - 1) as f64
+    } else {
+        0.
+    }
+}
+
+fn load_texture(path: &Option<String>) -> Result<Option<SharedTexture>, String> {
+    path.as_ref()
+        .map(|path| {
+            Texture::load(path)
+                .map(Arc::new)
+                .map_err(|e| format!("Не удалось загрузить {path}: {e}"))
+        })
+        .transpose()
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut models = args
+        .models
+        .iter()
+        .zip(&args.colors)
+        .map(|(path, color)| {
+            Object::load(path, color.clone())
+                .map_err(|e| format!("Не удалось загрузить {path}: {e}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    models[0].set_texture(load_texture(&args.textures.0)?);
+    models
+        .last_mut()
+        .unwrap()
+        .set_texture(load_texture(&args.textures.1)?);
+
+    for (path, obj) in args.models.iter().zip(models.iter_mut()) {
+        if args.crease_angle.is_some() {
+            obj.compute_normals(args.crease_angle);
+        }
+        if args.is_kernel_center {
+            match Kernel::new(obj).chebyshev_center(*obj.center()) {
+                Some((center, _)) => obj.set_projection_center(Some(center)),
+                None => eprintln!(
+                    "Предупреждение: {path}: ядро модели пусто, используется среднее вершин"
+                ),
+            }
+        }
+        if args.mapping == Mapping::Harmonic {
+            continue;
+        }
+        let report = StarReport::new(obj, obj.projection_center());
+        if !report.is_star_shaped() {
+            eprintln!("Предупреждение: {path}: {report}");
+        }
+    }
+
+    for &(a, b) in &args.pairs {
+        if a >= models[0].nvertexes() || b >= models[1].nvertexes() {
+            return Err(format!("Пара вершин {}:{} вне моделей", a + 1, b + 1));
+        }
+        models[0].add_landmark(a);
+        models[1].add_landmark(b);
+    }
+
+    let mut chain = MorphChain::new(models, |_, src, dst, pairs| {
+        let src_proj = Projection::with_mapping(src, SPHERE_RADIUS, args.mapping)
+            .map_err(|kind| Mesh::Source.error(kind))?;
+        let mut dst_proj = Projection::with_mapping(dst, SPHERE_RADIUS, args.mapping)
+            .map_err(|kind| Mesh::Target.error(kind))?;
+        if args.is_auto_align {
+            let rotation = RotationFit::new(&src_proj, &dst_proj).find(&Progress::new());
+            dst_proj.rotate_sphere(&rotation);
+        }
+        dst_proj
+            .align_to(&src_proj, &pairs)
+            .map_err(|kind| Mesh::Target.error(kind))?;
+        MergedObject::new(src_proj, dst_proj)
+    })
+    .map_err(|e| format!("Морфинг невозможен: {e}"))?;
+    chain.set_trajectory(args.trajectory);
+    chain.set_mask(args.mask);
+
+    if let Some(path) = &args.gltf {
+        let keyframes = Playback::new(args.duration).keyframes();
+        if let Some(mesh) = chain.morph_mesh(&keyframes) {
+            mesh.save(path)
+                .map_err(|e| format!("Не удалось сохранить {}: {e}", path.display()))?;
+        }
+    }
+    if args.frames == 0 && args.meshes == 0 {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&args.out)
+        .map_err(|e| format!("Не удалось создать {}: {e}", args.out.display()))?;
+    let mut canvas = Canvas::new(args.size.0, args.size.1, Color::new(BACKGROUND_COLOR));
+    canvas.set_sampler(args.sampler);
+    for i in 0..args.frames {
+        canvas.clear();
+        canvas.draw_object(
+            &chain.interpolation(progress(i, args.frames)),
+            args.light_direction,
+        );
+        let path = args.out.join(format!("frame_{i:04}.{}", args.format));
+        canvas
+            .save(&path)
+            .map_err(|e| format!("Не удалось сохранить {}: {e}", path.display()))?;
+    }
+    for i in 0..args.meshes {
+        let path = args.out.join(format!("mesh_{i:04}.obj"));
+        chain
+            .interpolation(progress(i, args.meshes))
+            .save(&path, true)
+            .map_err(|e| format!("Не удалось сохранить {}: {e}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("{e}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
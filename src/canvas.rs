@@ -1,7 +1,13 @@
-use std::{f64::MIN, mem::swap};
+use std::mem::swap;
+use std::path::Path;
 
 use crate::color::Color;
-use crate::figure::{object::Object, vertex::Vertex};
+use crate::figure::{
+    object::Object,
+    vertex::{TexCoord, Vertex},
+};
+use crate::texture::{Sampler, Texture};
+use crate::{AMBIENT_INTENSITY, EPS};
 
 pub struct Canvas {
     frame: Vec<u8>,
@@ -9,18 +15,20 @@ pub struct Canvas {
     height: u32,
     color: Color,
     zbuffer: Vec<f64>,
+    sampler: Sampler,
 }
 
 impl Canvas {
     pub fn new(width: u32, height: u32, color: Color) -> Self {
         let frame = vec![0; color.len() * (width * height) as usize];
-        let zbuffer = vec![MIN; (height * width) as usize];
+        let zbuffer = vec![f64::MIN; (height * width) as usize];
         let mut res = Self {
             frame,
             width,
             height,
             color,
             zbuffer,
+            sampler: Sampler::default(),
         };
         res.fill();
 
@@ -28,7 +36,7 @@ impl Canvas {
     }
 
     pub fn frame(&self) -> &[u8] {
-        &self.frame.as_slice()
+        self.frame.as_slice()
     }
 
     pub fn width(&self) -> u32 {
@@ -41,25 +49,178 @@ impl Canvas {
 
     pub fn clear(&mut self) {
         self.fill();
-        self.zbuffer = vec![MIN; (self.height * self.width) as usize];
+        self.zbuffer = vec![f64::MIN; (self.height * self.width) as usize];
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> image::ImageResult<()> {
+        let rgb = self
+            .frame
+            .chunks_exact(self.color.len())
+            .flat_map(|pixel| pixel[..3].iter().copied())
+            .collect::<Vec<_>>();
+        image::save_buffer(
+            path,
+            &rgb,
+            self.width,
+            self.height,
+            image::ExtendedColorType::Rgb8,
+        )
+    }
+
+    pub fn sampler(&self) -> Sampler {
+        self.sampler
+    }
+
+    pub fn set_sampler(&mut self, sampler: Sampler) {
+        self.sampler = sampler;
     }
 
     pub fn draw_object(&mut self, object: &Object, mut light_direction: Vertex) {
         light_direction.normalize();
+        // Blinn-Phong half vector, the viewer looks along the z axis
+        let mut half_vector = light_direction + Vertex::new(0., 0., 1.);
+        if half_vector.len() > EPS {
+            half_vector.normalize();
+        }
+        let object_texture = object.texture();
+        let has_texcoords = object.has_texcoords();
+        let has_vertex_colors = object.has_vertex_colors();
+        // translucent faces are blended over the opaque ones, from the farthest
+        let mut translucent = Vec::new();
         for face_ind in 0..object.nfaces() {
+            let surface = match object.face_material(face_ind) {
+                Some(material) => Surface {
+                    diffuse: material.diffuse.clone(),
+                    ambient: material.ambient.clone(),
+                    specular: material.specular.clone(),
+                    shininess: material.shininess,
+                    texture: material.texture.as_deref().or(object_texture.as_deref()),
+                    has_vertex_colors,
+                },
+                None => Surface {
+                    diffuse: object.color(),
+                    ambient: Color::new([0, 0, 0, 255]),
+                    specular: Color::new([0, 0, 0, 255]),
+                    shininess: 0.,
+                    texture: object_texture.as_deref(),
+                    has_vertex_colors,
+                },
+            };
+            // vertex colours already include the materials and textures they were baked from
+            let surface = Surface {
+                texture: surface
+                    .texture
+                    .filter(|_| has_texcoords && !has_vertex_colors),
+                ..surface
+            };
             let world_coords = object.face_coords(face_ind);
-            let mut intensities = vec![];
-            for normal in object.face_normals(face_ind) {
-                intensities.push(light_direction * normal);
-            }
+            let normals = object.face_normals(face_ind);
+            let texcoords = object
+                .face_texcoords(face_ind)
+                .unwrap_or_else(|| vec![(0., 0.); 3]);
+            let indexes = object.face_indexes(face_ind);
             let mut screen_coords = vec![];
             for i in 0..3 {
+                let specular = if surface.has_specular() {
+                    (half_vector * normals[i]).max(0.).powf(surface.shininess)
+                } else {
+                    0.
+                };
                 screen_coords.push((
                     world_coords[i].world_to_screen(self.height, self.width),
-                    intensities[i],
+                    Shade {
+                        intensity: light_direction * normals[i],
+                        specular,
+                        uv: texcoords[i],
+                        color: object
+                            .vertex_color(indexes[i].0)
+                            .unwrap_or_else(|| surface.diffuse.clone())
+                            .to_array()
+                            .map(f64::from),
+                    },
                 ));
             }
-            self.draw_triangle(screen_coords, object.color());
+            let is_translucent = if has_vertex_colors {
+                screen_coords.iter().any(|(_, shade)| shade.color[3] < 255.)
+            } else {
+                surface.diffuse.a() < 255 || surface.texture.is_some_and(|t| !t.is_opaque())
+            };
+            if is_translucent {
+                let depth = screen_coords.iter().map(|(v, _)| v.z).sum::<f64>() / 3.;
+                translucent.push((depth, screen_coords, surface));
+            } else {
+                self.draw_triangle(screen_coords, &surface);
+            }
+        }
+
+        translucent.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (_, screen_coords, surface) in translucent {
+            self.draw_triangle(screen_coords, &surface);
+        }
+    }
+}
+
+struct Surface<'a> {
+    diffuse: Color,
+    ambient: Color,
+    specular: Color,
+    shininess: f64,
+    texture: Option<&'a Texture>,
+    has_vertex_colors: bool,
+}
+
+impl Surface<'_> {
+    fn has_specular(&self) -> bool {
+        self.specular.r() > 0 || self.specular.g() > 0 || self.specular.b() > 0
+    }
+
+    fn shade(&self, shade: &Shade, sampler: Sampler) -> Color {
+        let base = match self.texture {
+            _ if self.has_vertex_colors => Color::new(shade.color.map(|c| c.round() as u8)),
+            Some(texture) => {
+                let texel = texture.sample(shade.uv, sampler);
+                let modulate = |t: u8, d: u8| (t as u16 * d as u16 / 255) as u8;
+                Color::new([
+                    modulate(texel.r(), self.diffuse.r()),
+                    modulate(texel.g(), self.diffuse.g()),
+                    modulate(texel.b(), self.diffuse.b()),
+                    modulate(texel.a(), self.diffuse.a()),
+                ])
+            }
+            None => self.diffuse.clone(),
+        };
+        let channel = |b: u8, a: u8, s: u8| {
+            (b as f64 * shade.intensity + a as f64 * AMBIENT_INTENSITY + s as f64 * shade.specular)
+                as u8
+        };
+
+        Color::new([
+            channel(base.r(), self.ambient.r(), self.specular.r()),
+            channel(base.g(), self.ambient.g(), self.specular.g()),
+            channel(base.b(), self.ambient.b(), self.specular.b()),
+            base.a(),
+        ])
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Shade {
+    intensity: f64,
+    specular: f64,
+    uv: TexCoord,
+    color: [f64; 4], // RGBA, kept unrounded between vertices
+}
+
+impl Shade {
+    fn interpolation(a: Shade, b: Shade, t: f64) -> Shade {
+        Shade {
+            intensity: a.intensity + (b.intensity - a.intensity) * t,
+            specular: a.specular + (b.specular - a.specular) * t,
+            uv: (
+                a.uv.0 + (b.uv.0 - a.uv.0) * t,
+                a.uv.1 + (b.uv.1 - a.uv.1) * t,
+            ),
+            color: [0, 1, 2, 3].map(|c| a.color[c] + (b.color[c] - a.color[c]) * t),
         }
     }
 }
@@ -71,12 +232,16 @@ impl Canvas {
         }
     }
 
-    fn draw_triangle(&mut self, mut coords: Vec<(Vertex, f64)>, color: Color) {
+    // The projection is orthographic, so attributes interpolated linearly
+    // in screen space are already perspective-correct.
+    fn draw_triangle(&mut self, mut coords: Vec<(Vertex, Shade)>, surface: &Surface) {
         if coords[0].0.y == coords[1].0.y && coords[1].0.y == coords[2].0.y {
             return;
         }
 
+        let lerp = Shade::interpolation;
         coords.sort_by(|a, b| a.0.y.partial_cmp(&b.0.y).expect("draw_triangle: sorting"));
+        let attrs = coords.iter().map(|c| c.1).collect::<Vec<_>>();
         let total_height = (coords[2].0.y - coords[0].0.y) as i32;
         for i in 0..total_height {
             let is_second_half =
@@ -87,7 +252,7 @@ impl Canvas {
                 coords[1].0.y - coords[0].0.y
             };
             let alpha = i as f64 / total_height as f64;
-            let mut a_side_intensity = coords[0].1 + (coords[2].1 - coords[0].1) * alpha;
+            let mut a_side_attr = lerp(attrs[0], attrs[2], alpha);
             let beta = if is_second_half {
                 (i as f64 - coords[1].0.y + coords[0].0.y) / segment_height
             } else {
@@ -99,16 +264,16 @@ impl Canvas {
             } else {
                 coords[0].0 + (coords[1].0 - coords[0].0) * beta
             };
-            let mut b_side_intensity = if is_second_half {
-                coords[1].1 + (coords[2].1 - coords[1].1) * beta
+            let mut b_side_attr = if is_second_half {
+                lerp(attrs[1], attrs[2], beta)
             } else {
-                coords[0].1 + (coords[1].1 - coords[0].1) * beta
+                lerp(attrs[0], attrs[1], beta)
             };
             a.round();
             b.round();
             if a.x > b.x {
                 swap(&mut a, &mut b);
-                swap(&mut a_side_intensity, &mut b_side_intensity);
+                swap(&mut a_side_attr, &mut b_side_attr);
             }
 
             for j in a.x as i32..=b.x as i32 {
@@ -121,22 +286,79 @@ impl Canvas {
                 p.round();
                 let idx = (p.x as u32 + p.y as u32 * self.width) as usize;
                 if idx < self.zbuffer.len() && self.zbuffer[idx] < p.z {
-                    self.zbuffer[idx] = p.z;
-                    let p_int = a_side_intensity + (b_side_intensity - a_side_intensity) * phi;
-                    let mut cur_color = color.clone();
-                    cur_color.set_rgb(
-                        (cur_color.r() as f64 * p_int) as u8,
-                        (cur_color.g() as f64 * p_int) as u8,
-                        (cur_color.b() as f64 * p_int) as u8,
-                    );
+                    let p_shade = lerp(a_side_attr, b_side_attr, phi);
+                    let cur_color = surface.shade(&p_shade, self.sampler);
+                    // translucent pixels leave the faces behind them visible
+                    if cur_color.a() == 255 {
+                        self.zbuffer[idx] = p.z;
+                    }
                     self.set_pixel(p.x.round() as u32, p.y.round() as u32, cur_color);
                 }
             }
         }
     }
 
+    // Translucent colours (`d` < 1 in MTL) are blended over the frame
     fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
         let pixel = color.len() * (x + y * self.width) as usize;
+        let color = if color.a() < 255 {
+            let frame = &self.frame[pixel..pixel + color.len()];
+            let under = Color::new([frame[0], frame[1], frame[2], frame[3]]);
+            let blended = Color::interpolation(under, color.clone(), color.a() as f64 / 255.);
+            Color::new([blended.r(), blended.g(), blended.b(), 255])
+        } else {
+            color
+        };
         self.frame[pixel..pixel + color.len()].copy_from_slice(&color.to_array());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::figure::material::Material;
+
+    // A translucent red triangle in front of an opaque blue one, in the given order
+    fn layers(is_front_first: bool) -> Object {
+        let vertexes = [0.5, 0.]
+            .into_iter()
+            .flat_map(|z| [(-1., -1.), (1., -1.), (0., 1.)].map(|(x, y)| Vertex::new(x, y, z)))
+            .collect();
+        let mut faces = vec![vec![(0, 0), (1, 0), (2, 0)], vec![(3, 0), (4, 0), (5, 0)]];
+        let mut face_materials = vec![Some(0), Some(1)];
+        if !is_front_first {
+            faces.reverse();
+            face_materials.reverse();
+        }
+
+        Object::new(
+            vertexes,
+            faces,
+            vec![Vertex::new(0., 0., 1.)],
+            Color::new([255; 4]),
+        )
+        .with_materials(
+            vec![
+                Material::from_color(Color::new([255, 0, 0, 128])),
+                Material::from_color(Color::new([0, 0, 255, 255])),
+            ],
+            face_materials,
+        )
+    }
+
+    #[test]
+    fn translucency_does_not_depend_on_the_order() {
+        let draw = |is_front_first| {
+            let mut canvas = Canvas::new(30, 30, Color::new([0, 0, 0, 255]));
+            canvas.draw_object(&layers(is_front_first), Vertex::new(0., 0., 1.));
+            canvas.frame().to_vec()
+        };
+        let frame = draw(true);
+        assert_eq!(frame, draw(false));
+
+        // the blue face shows through the red one
+        let pixel = 4 * (10 + 8 * 30);
+        let [r, g, b, _] = [0, 1, 2, 3].map(|c| frame[pixel + c]);
+        assert!(r > 100 && g == 0 && b > 100, "{:?}", (r, g, b));
+    }
+}
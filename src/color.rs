@@ -8,6 +8,12 @@ impl Color {
         Self(color[0], color[1], color[2], color[3])
     }
 
+    pub fn from_unit(r: f64, g: f64, b: f64, a: f64) -> Self {
+        let to_u8 = |c: f64| (c.clamp(0., 1.) * 255.).round() as u8;
+
+        Self(to_u8(r), to_u8(g), to_u8(b), to_u8(a))
+    }
+
     pub fn r(&self) -> u8 {
         self.0
     }
@@ -30,6 +36,7 @@ impl Color {
         self.3
     }
 
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         4
     }
@@ -3,5 +3,9 @@ pub const DEFAULT_SCALE: f64 = 3.;
 pub const BACKGROUND_COLOR: [u8; 4] = [0, 0, 0, 255];
 pub const SPHERE_RADIUS: f64 = 100.;
 pub const EPS: f64 = 1e-9;
-pub const RATIO_STEP: f64 = 0.01;
+pub const AMBIENT_INTENSITY: f64 = 0.1;
+pub const DEFAULT_DURATION: f64 = 2.; // of a morph pass, in seconds
 pub const DEFAULT_NOTIFY_DURATION: u64 = 5;
+pub const PICK_RADIUS: f32 = 6.; // in points, for picking vertexes with the mouse
+pub const DEFAULT_BRUSH_RADIUS: f32 = 20.; // in points, for painting the mask
+pub const DEFAULT_CREASE_ANGLE: f64 = 60.; // in degrees, for recomputed normals
@@ -0,0 +1,155 @@
+use std::f64::consts::PI;
+
+use super::{
+    matrix::{self, Matrix, IDENTITY},
+    progress::Progress,
+    projection::Projection,
+    vertex::Vertex,
+};
+use crate::EPS;
+
+const MAX_SAMPLES: usize = 400;
+const DIRECTIONS: usize = 30;
+const SPINS: usize = 12;
+const REFINED: usize = 4;
+const ICP_STEPS: usize = 50;
+const IMPROVEMENT: f64 = 0.9; // a rotation has to beat the identity by this factor
+
+// Rotation of the target sphere matching the shapes of both models around
+// their projection centers, so that similar models in different orientations
+// do not twist while morphing. The rotations spread evenly over all
+// orientations are scored and the best of them are refined by the iterative
+// closest point method.
+pub struct RotationFit {
+    src: Vec<Vertex>,
+    dst: Vec<Vertex>,
+}
+
+impl RotationFit {
+    pub fn new(src: &Projection, dst: &Projection) -> Self {
+        Self {
+            src: Self::samples(src),
+            dst: Self::samples(dst),
+        }
+    }
+
+    // Steps reported by `find`
+    pub fn nsteps() -> usize {
+        DIRECTIONS * SPINS + REFINED + 1
+    }
+
+    // Advances `progress` once per step, gives up with the identity if it is cancelled
+    pub fn find(&self, progress: &Progress) -> Matrix {
+        let mut starts = Vec::with_capacity(DIRECTIONS * SPINS);
+        for start in Self::starts() {
+            if progress.is_cancelled() {
+                return IDENTITY;
+            }
+            starts.push((self.error(&start), start));
+            progress.advance(1);
+        }
+        starts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // the identity is kept for symmetric models, which fit in many ways
+        let mut best = self.icp(IDENTITY);
+        progress.advance(1);
+        for &(_, start) in starts.iter().take(REFINED) {
+            if progress.is_cancelled() {
+                return IDENTITY;
+            }
+            let (error, rotation) = self.icp(start);
+            if error < best.0 * IMPROVEMENT {
+                best = (error, rotation);
+            }
+            progress.advance(1);
+        }
+
+        best.1
+    }
+}
+
+impl RotationFit {
+    // Vertexes around the projection center scaled to the unit mean square
+    // distance, thinned out for large models
+    fn samples(proj: &Projection) -> Vec<Vertex> {
+        let center = proj.projection_center();
+        let step = proj.nvertexes().div_ceil(MAX_SAMPLES).max(1);
+        let mut points = (0..proj.nvertexes())
+            .step_by(step)
+            .map(|ind| proj.vertex(ind) - center)
+            .collect::<Vec<_>>();
+        let scale = (points.iter().map(|p| p.len2()).sum::<f64>() / points.len() as f64).sqrt();
+        if scale > EPS {
+            for p in points.iter_mut() {
+                *p /= scale;
+            }
+        }
+
+        points
+    }
+
+    // The z axis is turned to the points of a Fibonacci spiral on the sphere
+    // after spinning around itself
+    fn starts() -> Vec<Matrix> {
+        let z = Vertex::new(0., 0., 1.);
+        let golden_angle = PI * (3. - 5f64.sqrt());
+        let mut starts = Vec::with_capacity(DIRECTIONS * SPINS);
+        for i in 0..DIRECTIONS {
+            let cos = 1. - 2. * (i as f64 + 0.5) / DIRECTIONS as f64;
+            let sin = (1. - cos * cos).sqrt();
+            let (y, x) = (golden_angle * i as f64).sin_cos();
+            let tilt = matrix::rotation_between(z, Vertex::new(x * sin, y * sin, cos));
+            for k in 0..SPINS {
+                let (s, c) = (2. * PI * k as f64 / SPINS as f64).sin_cos();
+                let spin = [[c, -s, 0.], [s, c, 0.], [0., 0., 1.]];
+                starts.push(matrix::multiply(&tilt, &spin));
+            }
+        }
+
+        starts
+    }
+
+    // Mean square distance from the turned target samples to the closest source samples
+    fn error(&self, rotation: &Matrix) -> f64 {
+        let sum = self
+            .dst
+            .iter()
+            .map(|&d| self.closest(matrix::transform(rotation, d)).0)
+            .sum::<f64>();
+
+        sum / self.dst.len().max(1) as f64
+    }
+
+    // Square distance to the closest source sample and the sample
+    fn closest(&self, v: Vertex) -> (f64, Vertex) {
+        self.src
+            .iter()
+            .map(|&s| ((s - v).len2(), s))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap_or((0., v))
+    }
+
+    // Rotation reached from `start` and its error
+    fn icp(&self, start: Matrix) -> (f64, Matrix) {
+        let mut rotation = start;
+        let mut error = f64::MAX;
+        for _ in 0..ICP_STEPS {
+            let mut m = [[0.; 3]; 3];
+            let mut sum = 0.;
+            for &d in self.dst.iter() {
+                let (dist, s) = self.closest(matrix::transform(&rotation, d));
+                sum += dist;
+                matrix::add_outer(&mut m, s, d);
+            }
+            let next_error = sum / self.dst.len().max(1) as f64;
+            let is_converged = error - next_error < EPS;
+            error = next_error;
+            if is_converged {
+                break;
+            }
+            rotation = matrix::orthogonal_fit(m, false);
+        }
+
+        (error, rotation)
+    }
+}
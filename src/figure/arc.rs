@@ -59,7 +59,7 @@ impl Arc {
             }
 
             let mut res = Vec::new();
-            for (v, id) in vec![(a.a, a.a_id), (a.b, a.b_id)] {
+            for (v, id) in [(a.a, a.a_id), (a.b, a.b_id)] {
                 if v == b.a {
                     res.push((id, 0.))
                 } else if v == b.b {
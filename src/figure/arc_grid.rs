@@ -0,0 +1,182 @@
+use super::{edge::Edge, edge_set::EdgeSet, vertex::Vertex};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+const MAX_CELLS: usize = 512;
+
+type Cell = (usize, usize, usize);
+
+// Uniform grid over the cube around the sphere. Arcs are sampled at half a
+// cell and stored in the cells of their samples, so two intersecting arcs
+// always have samples in neighbouring cells.
+pub struct ArcGrid {
+    radius: f64,
+    size: usize,
+    cell: f64,
+    cells: HashMap<Cell, Vec<Edge>>,
+}
+
+impl ArcGrid {
+    // `arc_len` is the typical arc length, cells are made about that size
+    pub fn new(radius: f64, arc_len: f64) -> Self {
+        let size = ((2. * radius / arc_len).ceil() as usize).clamp(1, MAX_CELLS);
+
+        Self {
+            radius,
+            size,
+            cell: 2. * radius / size as f64,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, edge: &Edge, a: Vertex, b: Vertex) {
+        let Some(edge) = edge.norm() else {
+            return;
+        };
+        for cell in self.arc_cells(a, b) {
+            self.cells.entry(cell).or_default().push(edge);
+        }
+    }
+
+    // Arcs of `edges` which may intersect the arc `a`-`b`, in the order of `edges`.
+    // Removed edges stay in the grid and are filtered out here.
+    pub fn candidates(&self, edges: &EdgeSet, a: Vertex, b: Vertex) -> Vec<Edge> {
+        let mut around = HashSet::new();
+        for (i, j, k) in self.arc_cells(a, b) {
+            for di in i.saturating_sub(1)..=(i + 1).min(self.size - 1) {
+                for dj in j.saturating_sub(1)..=(j + 1).min(self.size - 1) {
+                    for dk in k.saturating_sub(1)..=(k + 1).min(self.size - 1) {
+                        around.insert((di, dj, dk));
+                    }
+                }
+            }
+        }
+
+        let mut res = BTreeSet::new();
+        for cell in around {
+            if let Some(arcs) = self.cells.get(&cell) {
+                res.extend(arcs.iter().filter(|edge| edges.contains(edge)));
+            }
+        }
+
+        res.into_iter().collect()
+    }
+}
+
+impl ArcGrid {
+    fn cell_of(&self, v: Vertex) -> Cell {
+        let index =
+            |c: f64| (((c + self.radius) / self.cell).floor().max(0.) as usize).min(self.size - 1);
+
+        (index(v.x), index(v.y), index(v.z))
+    }
+
+    // Cells of points along the arc, no more than half a cell apart
+    fn arc_cells(&self, a: Vertex, b: Vertex) -> HashSet<Cell> {
+        let (mut a_unit, mut b_unit) = (a, b);
+        a_unit.normalize();
+        b_unit.normalize();
+        let angle = (a_unit * b_unit).clamp(-1., 1.).acos();
+        let len = a.len();
+        let steps = (2. * angle * len / self.cell).ceil().max(1.) as usize;
+
+        let mut cells = HashSet::new();
+        cells.insert(self.cell_of(a));
+        cells.insert(self.cell_of(b));
+        for step in 1..steps {
+            let t = step as f64 / steps as f64;
+            // spherical interpolation keeps the samples evenly spaced
+            let v = (a_unit * ((1. - t) * angle).sin() + b_unit * (t * angle).sin()) / angle.sin();
+            cells.insert(self.cell_of(v * len));
+        }
+
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::arc::{Arc, ArcIntersectionResult};
+    use super::*;
+
+    const RADIUS: f64 = 100.;
+
+    // Point on the sphere at the polar angle `theta` from the z axis and the longitude `phi`
+    fn polar(theta: f64, phi: f64) -> Vertex {
+        Vertex::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        ) * RADIUS
+    }
+
+    // Arcs of up to `max_angle` between pseudo-random points, and arcs around
+    // and over both poles
+    fn arcs(seed: u64, max_angle: f64) -> Vec<(Vertex, Vertex)> {
+        let mut state = seed;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+
+        let mut arcs = Vec::new();
+        while arcs.len() < 300 {
+            let a = polar(
+                (2. * random() - 1.).acos(),
+                2. * std::f64::consts::PI * random(),
+            );
+            let b = polar(
+                (2. * random() - 1.).acos(),
+                2. * std::f64::consts::PI * random(),
+            );
+            let (mut a_unit, mut b_unit) = (a, b);
+            a_unit.normalize();
+            b_unit.normalize();
+            if (a_unit * b_unit).acos() < max_angle {
+                arcs.push((a, b));
+            }
+        }
+        for pole in [0., std::f64::consts::PI] {
+            let theta = (pole - 0.02f64).abs();
+            for k in 0..8 {
+                let phi = k as f64 * std::f64::consts::PI / 4. + seed as f64 * 0.1;
+                arcs.push((polar(theta, phi), polar(theta, phi + 1.)));
+                arcs.push((polar(theta, phi), polar(theta, phi + std::f64::consts::PI)));
+            }
+        }
+
+        arcs
+    }
+
+    #[test]
+    fn candidates_include_every_intersection() {
+        for (max_angle, arc_len) in [(0.1, 5.), (0.5, 5.), (1., 20.)] {
+            let src = arcs(7, max_angle);
+            let dst = arcs(13, max_angle);
+            let mut grid = ArcGrid::new(RADIUS, arc_len);
+            let mut edges = EdgeSet::new();
+            for (i, &(a, b)) in src.iter().enumerate() {
+                let edge = Edge::new(2 * i, 2 * i + 1);
+                edges.insert(&edge);
+                grid.insert(&edge, a, b);
+            }
+
+            for (j, &(c, d)) in dst.iter().enumerate() {
+                let candidates = grid.candidates(&edges, c, d);
+                let dst_arc = Arc::new(c, d, 2 * (src.len() + j), 2 * (src.len() + j) + 1);
+                for (i, &(a, b)) in src.iter().enumerate() {
+                    let src_arc = Arc::new(a, b, 2 * i, 2 * i + 1);
+                    if matches!(Arc::intersect(&src_arc, &dst_arc), ArcIntersectionResult::N) {
+                        continue;
+                    }
+                    assert!(
+                        candidates.contains(&Edge::new(2 * i, 2 * i + 1)),
+                        "arc {i} crosses arc {j} but is not a candidate"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{color::Color, parallel, texture::SharedTexture};
+
+use super::{
+    material::Material,
+    morph_error::{Mesh, MeshErrorKind, MorphError},
+    object::Object,
+    overlay::Overlay,
+    progress::{Phase, Progress},
+    projection::Projection,
+    vertex::{TexCoord, Vertex},
+};
+
+// Base model and several targets on the common mesh of all their embeddings,
+// mixed with independent weights. The colours, UVs and materials are those of
+// the base, the targets only change the shape.
+pub struct BlendShape {
+    faces: Vec<Vec<usize>>,
+    vertexes: Vec<Vertex>, // of the base around its center
+    normals: Vec<Vertex>,
+    targets: Vec<(Vec<Vertex>, Vec<Vertex>)>, // offsets of the vertexes and the normals from the base
+    texcoords: Vec<TexCoord>,                 // empty if the base has no UVs
+    vertex_colors: Vec<Color>,                // empty if the base has no vertex colours
+    materials: Vec<Material>,                 // empty if the base has no materials
+    face_materials: Vec<Option<usize>>,
+    color: Color,
+    texture: Option<SharedTexture>,
+}
+
+// Failure of the target `target`, or of the base if there is none.
+// `Mesh::Source` in the error is the base, `Mesh::Target` is the target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlendError {
+    pub target: Option<usize>,
+    pub error: MorphError,
+}
+
+impl BlendShape {
+    // The targets are expected to be aligned to the base on the sphere already
+    pub fn with_progress(
+        base: Projection,
+        targets: Vec<Projection>,
+        progress: &Progress,
+    ) -> Result<Self, BlendError> {
+        let in_base = |kind| BlendError {
+            target: None,
+            error: Mesh::Source.error(kind),
+        };
+        let in_target = |target, kind| BlendError {
+            target: Some(target),
+            error: Mesh::Target.error(kind),
+        };
+        base.check().map_err(in_base)?;
+        for (k, target) in targets.iter().enumerate() {
+            target.check().map_err(|kind| in_target(k, kind))?;
+        }
+
+        let projs = [&base].into_iter().chain(&targets).collect::<Vec<_>>();
+        let overlay = Overlay::new(&projs, progress).map_err(|error| BlendError {
+            target: None,
+            error,
+        })?;
+        let sphere_vertexes = overlay.vertexes();
+
+        progress.start(Phase::BackProjection, sphere_vertexes.len() * projs.len());
+        // the surface point of each model under every vertex of the overlay
+        let points = parallel::try_map(sphere_vertexes, |vertex| {
+            projs
+                .iter()
+                .enumerate()
+                .map(|(model, proj)| {
+                    if progress.is_cancelled() {
+                        return Err(BlendError {
+                            target: None,
+                            error: MorphError::Cancelled,
+                        });
+                    }
+                    progress.advance(1);
+                    let point = match vertex.origin {
+                        Some((origin, index)) if origin == model => (
+                            proj.vertex(index),
+                            proj.normal(index),
+                            proj.texcoord(index),
+                            proj.vertex_color(index),
+                        ),
+                        _ => {
+                            let point = proj.project_from_sphere(vertex.vertex).map_err(
+                                |kind| match model {
+                                    0 => in_base(kind),
+                                    _ => in_target(model - 1, kind),
+                                },
+                            )?;
+                            (point.vertex, point.normal, point.texcoord, point.color)
+                        }
+                    };
+
+                    Ok(point)
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        let vertexes = points
+            .iter()
+            .map(|p| p[0].0 - *base.center())
+            .collect::<Vec<_>>();
+        let normals = points.iter().map(|p| p[0].1).collect::<Vec<_>>();
+        let targets = targets
+            .iter()
+            .enumerate()
+            .map(|(k, target)| {
+                let offsets = points
+                    .iter()
+                    .zip(&vertexes)
+                    .map(|(p, &v)| p[k + 1].0 - *target.center() - v)
+                    .collect();
+                let normal_offsets = points
+                    .iter()
+                    .zip(&normals)
+                    .map(|(p, &n)| p[k + 1].1 - n)
+                    .collect();
+                (offsets, normal_offsets)
+            })
+            .collect();
+        let texcoords = if points.iter().any(|p| p[0].2.is_some()) {
+            points.iter().map(|p| p[0].2.unwrap_or((0., 0.))).collect()
+        } else {
+            Vec::new()
+        };
+        let vertex_colors = if base.has_vertex_colors() {
+            points.iter().map(|p| p[0].3.clone()).collect()
+        } else {
+            Vec::new()
+        };
+
+        let sphere_vertexes: Vec<Vertex> = sphere_vertexes.iter().map(|v| v.vertex).collect();
+        progress.start(Phase::FaceResolution, 2);
+        if progress.is_cancelled() {
+            return Err(BlendError {
+                target: None,
+                error: MorphError::Cancelled,
+            });
+        }
+        let faces = overlay.triangles().map_err(|ind| BlendError {
+            target: None,
+            error: MorphError::Overlay {
+                mesh: Mesh::Source,
+                vertex: base.nearest_vertex(sphere_vertexes[ind]),
+            },
+        })?;
+        progress.advance(1);
+
+        let (materials, face_materials) =
+            Self::resolve_materials(&base, &sphere_vertexes, &faces).map_err(in_base)?;
+        progress.advance(1);
+
+        Ok(Self {
+            faces,
+            vertexes,
+            normals,
+            targets,
+            texcoords,
+            vertex_colors,
+            materials,
+            face_materials,
+            color: base.color(),
+            texture: base.texture(),
+        })
+    }
+
+    pub fn ntargets(&self) -> usize {
+        self.targets.len()
+    }
+
+    // The base moved by each target offset times its weight, the missing
+    // weights are taken as zeros
+    pub fn interpolation(&self, weights: &[f64]) -> Object {
+        let mut vertexes = self.vertexes.clone();
+        let mut normals = self.normals.clone();
+        for ((offsets, normal_offsets), &weight) in self.targets.iter().zip(weights) {
+            if weight == 0. {
+                continue;
+            }
+            for (v, &d) in vertexes.iter_mut().zip(offsets) {
+                *v += d * weight;
+            }
+            for (n, &d) in normals.iter_mut().zip(normal_offsets) {
+                *n += d * weight;
+            }
+        }
+        let faces = self
+            .faces
+            .iter()
+            .map(|f| f.iter().map(|&v| (v, v)).collect())
+            .collect();
+        let face_texcoords = if self.texcoords.is_empty() {
+            Vec::new()
+        } else {
+            self.faces.clone()
+        };
+
+        let mut object = Object::new(vertexes, faces, normals, self.color.clone())
+            .with_texcoords(self.texcoords.clone(), face_texcoords);
+        object.set_texture(self.texture.clone());
+        if !self.materials.is_empty() {
+            object = object.with_materials(self.materials.clone(), self.face_materials.clone());
+        }
+        if !self.vertex_colors.is_empty() {
+            object = object.with_vertex_colors(self.vertex_colors.clone());
+        }
+        object
+    }
+}
+
+impl BlendShape {
+    // Each face of the overlay lies inside one face of the base, found by its centroid
+    fn resolve_materials(
+        base: &Projection,
+        verts: &[Vertex],
+        faces: &[Vec<usize>],
+    ) -> Result<(Vec<Material>, Vec<Option<usize>>), MeshErrorKind> {
+        if !base.has_materials() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+
+        let base_faces = parallel::try_map(faces, |face| {
+            let mut centroid = Vertex::default();
+            for &v in face.iter() {
+                centroid += verts[v];
+            }
+            base.project_from_sphere(centroid).map(|point| point.face)
+        })?;
+        let mut indexes = BTreeMap::new();
+        let mut materials = Vec::new();
+        let face_materials = base_faces
+            .into_iter()
+            .map(|face| {
+                let material = base.face_material_index(face)?;
+                Some(*indexes.entry(material).or_insert_with(|| {
+                    materials.push(base.face_material(face));
+                    materials.len() - 1
+                }))
+            })
+            .collect();
+
+        Ok((materials, face_materials))
+    }
+}
+
+impl fmt::Display for BlendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            MorphError::Mesh {
+                mesh: Mesh::Source,
+                kind,
+            } => write!(f, "базовая модель: {kind}"),
+            MorphError::Mesh {
+                mesh: Mesh::Target,
+                kind,
+            } => write!(f, "цель {}: {kind}", self.target.unwrap_or(0) + 1),
+            error => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for BlendError {}
@@ -0,0 +1,279 @@
+use super::{object::Object, vertex::Vertex};
+
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy)]
+struct Bounds {
+    min: Vertex,
+    max: Vertex,
+}
+
+impl Bounds {
+    fn empty() -> Self {
+        Self {
+            min: Vertex::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vertex::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    fn add(&mut self, v: Vertex) {
+        self.min = Vertex::new(
+            self.min.x.min(v.x),
+            self.min.y.min(v.y),
+            self.min.z.min(v.z),
+        );
+        self.max = Vertex::new(
+            self.max.x.max(v.x),
+            self.max.y.max(v.y),
+            self.max.z.max(v.z),
+        );
+    }
+
+    fn union(&mut self, other: &Bounds) {
+        self.add(other.min);
+        self.add(other.max);
+    }
+
+    // Boxes are padded so that rays through shared edges still reach every
+    // face `Triangle::contains` accepts
+    fn pad(&mut self) {
+        let margin = 1e-7 * (1. + (self.max - self.min).len());
+        let delta = Vertex::new(margin, margin, margin);
+        self.min -= delta;
+        self.max += delta;
+    }
+
+    // Slab test for the ray `origin + dir * t`, t >= 0
+    fn hit(&self, origin: Vertex, dir: Vertex) -> bool {
+        let mut t_min = 0f64;
+        let mut t_max = f64::INFINITY;
+        for (o, d, lo, hi) in [
+            (origin.x, dir.x, self.min.x, self.max.x),
+            (origin.y, dir.y, self.min.y, self.max.y),
+            (origin.z, dir.z, self.min.z, self.max.z),
+        ] {
+            if d == 0. {
+                if o < lo || o > hi {
+                    return false;
+                }
+                continue;
+            }
+            let (t1, t2) = ((lo - o) / d, (hi - o) / d);
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+            if t_min > t_max {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Node {
+    Leaf {
+        bounds: Bounds,
+        start: usize,
+        end: usize,
+    },
+    Inner {
+        bounds: Bounds,
+        left: usize,
+        right: usize,
+    },
+}
+
+// Bounding volume hierarchy over the faces of an object, answers which
+// faces a ray may hit without testing all of them
+#[derive(Clone)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    faces: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(triangles: &[[Vertex; 3]]) -> Self {
+        let mut bounds = Vec::with_capacity(triangles.len());
+        let mut centroids = Vec::with_capacity(triangles.len());
+        for tri in triangles.iter() {
+            let mut b = Bounds::empty();
+            tri.iter().for_each(|&v| b.add(v));
+            b.pad();
+            bounds.push(b);
+            centroids.push((tri[0] + tri[1] + tri[2]) / 3.);
+        }
+
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            faces: (0..triangles.len()).collect(),
+        };
+        if !triangles.is_empty() {
+            bvh.build(&bounds, &centroids, 0, triangles.len());
+        }
+
+        bvh
+    }
+
+    pub fn from_object(object: &Object) -> Self {
+        let triangles = (0..object.nfaces())
+            .map(|ind| {
+                let coords = object.face_coords(ind);
+                [coords[0], coords[1], coords[2]]
+            })
+            .collect::<Vec<_>>();
+
+        Self::new(&triangles)
+    }
+
+    // Calls `f` for each face whose box is crossed by the ray from `origin` along `dir`
+    pub fn query<F: FnMut(usize)>(&self, origin: Vertex, dir: Vertex, mut f: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(ind) = stack.pop() {
+            match &self.nodes[ind] {
+                Node::Leaf { bounds, start, end } => {
+                    if bounds.hit(origin, dir) {
+                        self.faces[*start..*end].iter().for_each(|&face| f(face));
+                    }
+                }
+                Node::Inner {
+                    bounds,
+                    left,
+                    right,
+                } => {
+                    if bounds.hit(origin, dir) {
+                        stack.push(*right);
+                        stack.push(*left);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Bvh {
+    fn build(
+        &mut self,
+        bounds: &[Bounds],
+        centroids: &[Vertex],
+        start: usize,
+        end: usize,
+    ) -> usize {
+        let mut node_bounds = Bounds::empty();
+        let mut centroid_bounds = Bounds::empty();
+        for &face in self.faces[start..end].iter() {
+            node_bounds.union(&bounds[face]);
+            centroid_bounds.add(centroids[face]);
+        }
+
+        let ind = self.nodes.len();
+        if end - start <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf {
+                bounds: node_bounds,
+                start,
+                end,
+            });
+            return ind;
+        }
+
+        let axis = centroid_bounds.axis();
+        let key = |face: &usize| {
+            let c = centroids[*face];
+            [c.x, c.y, c.z][axis]
+        };
+        let mid = (start + end) / 2;
+        self.faces[start..end]
+            .select_nth_unstable_by(mid - start, |a, b| key(a).total_cmp(&key(b)));
+
+        // children are filled in after they are built
+        self.nodes.push(Node::Leaf {
+            bounds: node_bounds,
+            start,
+            end,
+        });
+        let left = self.build(bounds, centroids, start, mid);
+        let right = self.build(bounds, centroids, mid, end);
+        self.nodes[ind] = Node::Inner {
+            bounds: node_bounds,
+            left,
+            right,
+        };
+
+        ind
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::triangle::Triangle;
+    use super::*;
+    use crate::color::Color;
+
+    // First face hit by the ray, the one with the lowest index on ties
+    fn first_hit(
+        triangles: &[Triangle],
+        origin: Vertex,
+        dir: Vertex,
+        query: impl Fn(&mut dyn FnMut(usize)),
+    ) -> Option<usize> {
+        let mut hit = None;
+        query(&mut |ind| {
+            if hit.is_some_and(|first| first < ind) {
+                return;
+            }
+            if triangles[ind].intersect(origin, origin + dir).is_some() {
+                hit = Some(ind);
+            }
+        });
+        hit
+    }
+
+    #[test]
+    fn query_matches_linear_scan() {
+        for path in ["./models/cube.obj", "./models/cylinder.obj"] {
+            let object = Object::load(path, Color::new([255; 4])).unwrap();
+            let bvh = Bvh::from_object(&object);
+            let triangles = (0..object.nfaces())
+                .map(|ind| {
+                    let coords = object.face_coords(ind);
+                    Triangle::new(coords[0], coords[1], coords[2])
+                })
+                .collect::<Vec<_>>();
+            let origin = object.projection_center();
+
+            // vertexes and edge middles lie on several faces, centroids on one
+            let mut targets = object.vertexes_iter().copied().collect::<Vec<_>>();
+            for ind in 0..object.nfaces() {
+                let c = object.face_coords(ind);
+                targets.extend([
+                    (c[0] + c[1]) / 2.,
+                    (c[1] + c[2]) / 2.,
+                    (c[0] + c[1] + c[2]) / 3.,
+                ]);
+            }
+            for target in targets {
+                let dir = target - origin;
+                let indexed = first_hit(&triangles, origin, dir, |f| bvh.query(origin, dir, f));
+                let scanned = first_hit(&triangles, origin, dir, |f| {
+                    (0..triangles.len()).for_each(&mut *f)
+                });
+                assert!(scanned.is_some(), "{path}: the ray misses the model");
+                assert_eq!(indexed, scanned, "{path}");
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use super::edge::Edge;
 use std::collections::{btree_set::Iter, BTreeSet};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct EdgeSet(BTreeSet<Edge>);
 
 impl EdgeSet {
@@ -27,6 +27,10 @@ impl EdgeSet {
         }
     }
 
+    pub fn contains(&self, edge: &Edge) -> bool {
+        edge.norm().is_some_and(|edge| self.0.contains(&edge))
+    }
+
     pub fn iter(&self) -> Iter<'_, Edge> {
         self.0.iter()
     }
@@ -0,0 +1,361 @@
+use super::vertex::{TexCoord, Vertex};
+use crate::color::Color;
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+const GLB_MAGIC: u32 = 0x4654_6c67; // "glTF"
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// Mesh with a single morph target and the animation of its weight, written
+// as binary glTF 2.0, so the morph plays in any glTF viewer
+pub struct MorphMesh {
+    pub positions: Vec<Vertex>,
+    pub normals: Vec<Vertex>,
+    pub texcoords: Vec<TexCoord>, // empty if there are no UVs
+    pub colors: Vec<Color>,       // empty if there are no vertex colours
+    pub position_deltas: Vec<Vertex>,
+    pub normal_deltas: Vec<Vertex>,
+    pub indexes: Vec<u32>,
+    pub color: Color,
+    pub keyframes: Vec<(f64, f64)>, // (seconds, weight of the target)
+}
+
+// Binary chunk with the buffer views and the accessors describing it
+#[derive(Default)]
+struct Buffer {
+    data: Vec<u8>,
+    views: Vec<String>,
+    accessors: Vec<String>,
+}
+
+impl MorphMesh {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+
+        writer.flush()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut buffer = Buffer::default();
+        let vec3 = |vs: &[Vertex]| {
+            vs.iter()
+                .flat_map(|v| [v.x, v.y, v.z])
+                .map(|c| c as f32)
+                .collect::<Vec<_>>()
+        };
+
+        let mut attributes = vec![
+            format!(
+                "\"POSITION\":{}",
+                buffer.add_floats(&vec3(&self.positions), 3, true, Some(ARRAY_BUFFER))
+            ),
+            format!(
+                "\"NORMAL\":{}",
+                buffer.add_floats(&vec3(&self.normals), 3, false, Some(ARRAY_BUFFER))
+            ),
+        ];
+        if !self.texcoords.is_empty() {
+            // the V axis of glTF goes down the image, unlike in OBJ
+            let uvs = self
+                .texcoords
+                .iter()
+                .flat_map(|&(u, v)| [u as f32, 1. - v as f32])
+                .collect::<Vec<_>>();
+            let accessor = buffer.add_floats(&uvs, 2, false, Some(ARRAY_BUFFER));
+            attributes.push(format!("\"TEXCOORD_0\":{accessor}"));
+        }
+        if !self.colors.is_empty() {
+            let colors = self
+                .colors
+                .iter()
+                .flat_map(|c| c.to_array())
+                .map(|c| c as f32 / 255.)
+                .collect::<Vec<_>>();
+            let accessor = buffer.add_floats(&colors, 4, false, Some(ARRAY_BUFFER));
+            attributes.push(format!("\"COLOR_0\":{accessor}"));
+        }
+        let position_deltas =
+            buffer.add_floats(&vec3(&self.position_deltas), 3, true, Some(ARRAY_BUFFER));
+        let normal_deltas =
+            buffer.add_floats(&vec3(&self.normal_deltas), 3, false, Some(ARRAY_BUFFER));
+        let indexes = buffer.add_indexes(&self.indexes);
+
+        // a morph without keyframes stays at its start weight, with no animation
+        let animations = if self.keyframes.is_empty() {
+            String::new()
+        } else {
+            let times = self
+                .keyframes
+                .iter()
+                .map(|&(time, _)| time as f32)
+                .collect::<Vec<_>>();
+            let weights = self
+                .keyframes
+                .iter()
+                .map(|&(_, weight)| weight as f32)
+                .collect::<Vec<_>>();
+            format!(
+                concat!(
+                    "\"animations\":[{{\"name\":\"morph\",",
+                    "\"samplers\":[{{\"input\":{},\"output\":{},\"interpolation\":\"LINEAR\"}}],",
+                    "\"channels\":[{{\"sampler\":0,\"target\":{{\"node\":0,\"path\":\"weights\"}}}}]}}],"
+                ),
+                buffer.add_floats(&times, 1, true, None),
+                buffer.add_floats(&weights, 1, false, None),
+            )
+        };
+        let start_weight = self.keyframes.first().map_or(0., |&(_, weight)| weight);
+
+        let unit = |c: u8| c as f32 / 255.;
+        let json = format!(
+            concat!(
+                "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"Morphing\"}},",
+                "\"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],",
+                "\"nodes\":[{{\"mesh\":0,\"name\":\"morph\"}}],",
+                "\"meshes\":[{{\"primitives\":[{{\"attributes\":{{{}}},\"indices\":{},",
+                "\"material\":0,\"targets\":[{{\"POSITION\":{},\"NORMAL\":{}}}]}}],",
+                "\"weights\":[{}]}}],",
+                "\"materials\":[{{\"pbrMetallicRoughness\":{{\"baseColorFactor\":[{},{},{},{}],",
+                "\"metallicFactor\":0,\"roughnessFactor\":1}},\"doubleSided\":true}}],",
+                "{}",
+                "\"buffers\":[{{\"byteLength\":{}}}],",
+                "\"bufferViews\":[{}],\"accessors\":[{}]}}"
+            ),
+            attributes.join(","),
+            indexes,
+            position_deltas,
+            normal_deltas,
+            start_weight,
+            unit(self.color.r()),
+            unit(self.color.g()),
+            unit(self.color.b()),
+            unit(self.color.a()),
+            animations,
+            buffer.data.len(),
+            buffer.views.join(","),
+            buffer.accessors.join(","),
+        );
+
+        Self::write_glb(writer, json.into_bytes(), buffer.data)
+    }
+}
+
+impl MorphMesh {
+    // Header and two chunks, each padded to 4 bytes
+    fn write_glb<W: Write>(writer: &mut W, mut json: Vec<u8>, mut bin: Vec<u8>) -> io::Result<()> {
+        json.resize(json.len().next_multiple_of(4), b' ');
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+
+        for word in [GLB_MAGIC, GLB_VERSION, length as u32] {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        for (kind, chunk) in [(CHUNK_JSON, &json), (CHUNK_BIN, &bin)] {
+            writer.write_all(&(chunk.len() as u32).to_le_bytes())?;
+            writer.write_all(&kind.to_le_bytes())?;
+            writer.write_all(chunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Buffer {
+    // Accessor of elements of `size` floats, without a `target` for the data
+    // which is not read by the GPU. The bounds are required for positions and times.
+    fn add_floats(
+        &mut self,
+        values: &[f32],
+        size: usize,
+        with_bounds: bool,
+        target: Option<u32>,
+    ) -> usize {
+        let kind = match size {
+            1 => "SCALAR".to_string(),
+            _ => format!("VEC{size}"),
+        };
+        let bounds = if with_bounds && !values.is_empty() {
+            let mut min = values[..size].to_vec();
+            let mut max = min.clone();
+            for element in values.chunks(size) {
+                for (k, &v) in element.iter().enumerate() {
+                    min[k] = min[k].min(v);
+                    max[k] = max[k].max(v);
+                }
+            }
+            let list = |vs: Vec<f32>| {
+                vs.iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            format!(",\"min\":[{}],\"max\":[{}]", list(min), list(max))
+        } else {
+            String::new()
+        };
+
+        let view = self.add_view(
+            values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            target,
+        );
+        self.accessors.push(format!(
+            "{{\"bufferView\":{view},\"componentType\":{FLOAT},\"count\":{},\"type\":\"{kind}\"{bounds}}}",
+            values.len() / size
+        ));
+
+        self.accessors.len() - 1
+    }
+
+    fn add_indexes(&mut self, indexes: &[u32]) -> usize {
+        let view = self.add_view(
+            indexes.iter().flat_map(|i| i.to_le_bytes()).collect(),
+            Some(ELEMENT_ARRAY_BUFFER),
+        );
+        self.accessors.push(format!(
+            "{{\"bufferView\":{view},\"componentType\":{UNSIGNED_INT},\"count\":{},\"type\":\"SCALAR\"}}",
+            indexes.len()
+        ));
+
+        self.accessors.len() - 1
+    }
+
+    // All the components are 4 bytes long, so the views stay aligned
+    fn add_view(&mut self, bytes: Vec<u8>, target: Option<u32>) -> usize {
+        let target = target
+            .map(|target| format!(",\"target\":{target}"))
+            .unwrap_or_default();
+        self.views.push(format!(
+            "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{}{target}}}",
+            self.data.len(),
+            bytes.len()
+        ));
+        self.data.extend(bytes);
+
+        self.views.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh() -> MorphMesh {
+        let v = Vertex::new;
+        MorphMesh {
+            positions: vec![v(0., 0., 0.), v(1., 0., 0.), v(0., 2., 0.)],
+            normals: vec![v(0., 0., 1.); 3],
+            texcoords: vec![(0., 0.), (1., 0.), (0., 1.)],
+            colors: vec![Color::new([255, 0, 0, 255]); 3],
+            position_deltas: vec![v(0., 0., 1.), v(0., 0., -1.), v(0., 0., 0.)],
+            normal_deltas: vec![v(0., 0., 0.); 3],
+            indexes: vec![0, 1, 2, 2, 1, 0],
+            color: Color::new([255; 4]),
+            keyframes: vec![(0., 0.), (2., 1.)],
+        }
+    }
+
+    fn word(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    // Values of every `"key":N` in the JSON, in order
+    fn numbers(json: &str, key: &str) -> Vec<usize> {
+        let pattern = format!("\"{key}\":");
+        json.match_indices(&pattern)
+            .map(|(at, _)| {
+                let rest = &json[at + pattern.len()..];
+                let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap();
+                rest[..end].parse().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn glb_layout() {
+        let mut glb = Vec::new();
+        mesh().write(&mut glb).unwrap();
+
+        assert_eq!(word(&glb, 0), GLB_MAGIC);
+        assert_eq!(word(&glb, 4), GLB_VERSION);
+        assert_eq!(word(&glb, 8) as usize, glb.len());
+
+        let json_len = word(&glb, 12) as usize;
+        assert_eq!(word(&glb, 16), CHUNK_JSON);
+        assert_eq!(json_len % 4, 0);
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        // the chunk is padded with spaces, which JSON ignores
+        assert!(json.trim_end().ends_with('}'));
+
+        let bin_at = 20 + json_len;
+        let bin_len = word(&glb, bin_at) as usize;
+        assert_eq!(word(&glb, bin_at + 4), CHUNK_BIN);
+        assert_eq!(bin_len % 4, 0);
+        assert_eq!(bin_at + 8 + bin_len, glb.len());
+
+        // positions, normals, UVs, colours, two deltas, indexes, times and weights
+        assert_eq!(numbers(json, "count"), vec![3, 3, 3, 3, 3, 3, 6, 2, 2]);
+        // the buffer goes first, then its views one after another
+        let lengths = numbers(json, "byteLength");
+        assert_eq!(lengths[1..], [36, 36, 24, 48, 36, 36, 24, 8, 8]);
+        assert_eq!(lengths[0], lengths[1..].iter().sum::<usize>());
+        assert!(lengths[0] <= bin_len);
+        let offsets = numbers(json, "byteOffset");
+        let mut offset = 0;
+        for (&at, &len) in offsets.iter().zip(&lengths[1..]) {
+            assert_eq!(at, offset);
+            assert_eq!(at % 4, 0);
+            offset += len;
+        }
+
+        // the data starts with the positions
+        let bin = &glb[bin_at + 8..];
+        let float = |at: usize| f32::from_le_bytes(bin[at..at + 4].try_into().unwrap());
+        assert_eq!((float(12), float(28)), (1., 2.));
+        assert!(json.contains("\"min\":[0,0,0],\"max\":[1,2,0]"));
+    }
+
+    #[test]
+    fn optional_attributes() {
+        let mesh = MorphMesh {
+            texcoords: Vec::new(),
+            colors: Vec::new(),
+            ..mesh()
+        };
+        let mut glb = Vec::new();
+        mesh.write(&mut glb).unwrap();
+        let json_len = word(&glb, 12) as usize;
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+
+        assert!(!json.contains("TEXCOORD_0") && !json.contains("COLOR_0"));
+        assert_eq!(numbers(json, "count"), vec![3, 3, 3, 3, 6, 2, 2]);
+        assert_eq!(word(&glb, 8) as usize, glb.len());
+    }
+
+    #[test]
+    fn no_keyframes_no_animation() {
+        let mesh = MorphMesh {
+            keyframes: Vec::new(),
+            ..mesh()
+        };
+        let mut glb = Vec::new();
+        mesh.write(&mut glb).unwrap();
+        let json_len = word(&glb, 12) as usize;
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+
+        assert!(!json.contains("animations"));
+        assert!(json.contains("\"weights\":[0]"));
+        assert_eq!(numbers(json, "count"), vec![3, 3, 3, 3, 3, 3, 6]);
+        assert_eq!(word(&glb, 8) as usize, glb.len());
+    }
+}
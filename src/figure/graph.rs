@@ -14,13 +14,12 @@ pub struct Graph {
 }
 
 impl Graph {
-    pub fn new<T: Ord>(nodes: &Vec<T>) -> Self {
+    pub fn new<T: Ord>(nodes: &[T]) -> Self {
         let n = nodes.len();
         let mut index_map = Vec::with_capacity(n);
         let mut map = BTreeMap::<&T, usize>::new();
 
-        for i in 0..n {
-            let v = &nodes[i];
+        for (i, v) in nodes.iter().enumerate() {
             let id = if let Some(&id) = map.get(&v) { id } else { i };
             map.insert(v, id);
             index_map.push(id);
@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::f64::consts::PI;
+
+use super::{
+    edge::Edge, matrix, morph_error::MeshErrorKind, object::Object, triangle::Triangle,
+    vertex::Vertex,
+};
+use crate::EPS;
+
+const SOLVER_STEPS: usize = 10000;
+const BALANCE_STEPS: usize = 200;
+const UNTANGLE_STEPS: usize = 100;
+
+// Map of a closed genus 0 model onto the sphere which needs no star-shaped
+// center. The model without its first face is embedded into a triangle by
+// Tutte's barycentric method, lifted by the inverse stereographic projection
+// and spread over the sphere by Möbius transformations.
+pub struct HarmonicMap {
+    neighbors: Vec<Vec<usize>>,
+    areas: Vec<f64>, // third of the area of the faces around each vertex
+}
+
+impl HarmonicMap {
+    pub fn new(object: &Object) -> Result<Self, MeshErrorKind> {
+        let n = object.nvertexes();
+        let mut neighbors = vec![BTreeSet::new(); n];
+        let mut areas = vec![0.; n];
+        let mut edge_faces = BTreeMap::new();
+        for ind in 0..object.nfaces() {
+            let face = object.face_indexes(ind);
+            let coords = object.face_coords(ind);
+            let area = Triangle::new(coords[0], coords[1], coords[2])
+                .normal()
+                .len()
+                / 2.;
+            for (k, l) in [(0, 1), (1, 2), (2, 0)] {
+                let (a, b) = (face[k].0, face[l].0);
+                neighbors[a].insert(b);
+                neighbors[b].insert(a);
+                if let Some(edge) = Edge::new(a, b).norm() {
+                    *edge_faces.entry(edge).or_insert(0) += 1;
+                }
+                areas[a] += area / 3.;
+            }
+        }
+        let neighbors = neighbors
+            .into_iter()
+            .map(|set| set.into_iter().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        if let Some((edge, _)) = edge_faces.iter().find(|(_, count)| **count < 2) {
+            return Err(MeshErrorKind::Boundary(edge.from, edge.to));
+        }
+        // components come first: a sphere with a torus passes Euler's formula,
+        // and separate spheres are better reported as disconnected
+        let start = object.face_indexes(0)[0].0;
+        let mut is_reached = vec![false; n];
+        is_reached[start] = true;
+        let mut stack = vec![start];
+        while let Some(v) = stack.pop() {
+            for &u in neighbors[v].iter() {
+                if !is_reached[u] {
+                    is_reached[u] = true;
+                    stack.push(u);
+                }
+            }
+        }
+        if let Some(v) = (0..n).find(|&v| !is_reached[v] && !neighbors[v].is_empty()) {
+            return Err(MeshErrorKind::Disconnected(v));
+        }
+
+        let nused = neighbors.iter().filter(|nbrs| !nbrs.is_empty()).count();
+        let euler = nused as i64 - edge_faces.len() as i64 + object.nfaces() as i64;
+        if euler != 2 {
+            return Err(MeshErrorKind::NotSphere(euler));
+        }
+
+        Ok(Self { neighbors, areas })
+    }
+
+    // Vertexes on the sphere of `radius` around the origin, turned to match
+    // the directions from the projection center. Vertexes outside of faces are
+    // projected from the center as usual.
+    pub fn embed(&self, object: &Object, radius: f64) -> Result<Vec<Vertex>, MeshErrorKind> {
+        let center = object.projection_center();
+        let plane = self.tutte(object);
+        let mut sphere = plane
+            .iter()
+            .map(|p| {
+                let len2 = p.len2();
+                Vertex::new(2. * p.x, 2. * p.y, len2 - 1.) / (len2 + 1.)
+            })
+            .collect::<Vec<_>>();
+        self.balance(&mut sphere);
+        self.untangle(object, &plane, &mut sphere)?;
+
+        let mut m = [[0.; 3]; 3];
+        for (v, x) in sphere.iter().enumerate() {
+            let d = object.vertex(v) - center;
+            if self.neighbors[v].is_empty() || d.len() < EPS {
+                continue;
+            }
+            matrix::add_outer(&mut m, d * (self.areas[v] / d.len()), *x);
+        }
+        let rotation = matrix::orthogonal_fit(m, true);
+
+        Ok(sphere
+            .into_iter()
+            .enumerate()
+            .map(|(v, x)| {
+                if self.neighbors[v].is_empty() {
+                    object.vertex(v).project_to_sphere(center, radius)
+                } else {
+                    matrix::transform(&rotation, x) * radius
+                }
+            })
+            .collect())
+    }
+}
+
+impl HarmonicMap {
+    // Every inner vertex is the mean of its neighbours, which gives a flat
+    // embedding without folds for a triangulated sphere
+    fn tutte(&self, object: &Object) -> Vec<Vertex> {
+        let n = self.neighbors.len();
+        let mut plane = vec![Vertex::new(0., 0., 0.); n];
+        let face = object.face_indexes(0);
+        let outer = [face[0].0, face[1].0, face[2].0];
+        for (k, &v) in outer.iter().enumerate() {
+            let (sin, cos) = (2. * PI * k as f64 / 3.).sin_cos();
+            plane[v] = Vertex::new(cos, sin, 0.);
+        }
+
+        let inner = (0..n)
+            .filter(|v| !self.neighbors[*v].is_empty() && !outer.contains(v))
+            .collect::<Vec<_>>();
+        let mut index = vec![None; n];
+        for (i, &v) in inner.iter().enumerate() {
+            index[v] = Some(i);
+        }
+        // neighbours of the inner vertexes by their inner index, the outer ones go to the right side
+        let rows = inner
+            .iter()
+            .map(|&v| self.neighbors[v].iter().filter_map(|&u| index[u]).collect())
+            .collect::<Vec<Vec<usize>>>();
+        let degree = inner
+            .iter()
+            .map(|&v| self.neighbors[v].len() as f64)
+            .collect::<Vec<_>>();
+        let rhs = inner
+            .iter()
+            .map(|&v| {
+                self.neighbors[v]
+                    .iter()
+                    .filter(|&&u| index[u].is_none())
+                    .fold(Vertex::new(0., 0., 0.), |sum, &u| sum + plane[u])
+            })
+            .collect::<Vec<_>>();
+        let xs = Self::solve(&rows, &degree, rhs.iter().map(|b| b.x).collect());
+        let ys = Self::solve(&rows, &degree, rhs.iter().map(|b| b.y).collect());
+        for (i, &v) in inner.iter().enumerate() {
+            plane[v] = Vertex::new(xs[i], ys[i], 0.);
+        }
+
+        plane
+    }
+
+    // Conjugate gradients with the Jacobi preconditioner for the graph Laplacian
+    // given by the inner `rows` and the full `degree` of each vertex
+    fn solve(rows: &[Vec<usize>], degree: &[f64], rhs: Vec<f64>) -> Vec<f64> {
+        let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+        let apply = |x: &[f64]| {
+            rows.iter()
+                .zip(degree)
+                .enumerate()
+                .map(|(i, (row, d))| d * x[i] - row.iter().map(|&j| x[j]).sum::<f64>())
+                .collect::<Vec<_>>()
+        };
+
+        let tolerance = 1e-24 * dot(&rhs, &rhs);
+        let mut x = vec![0.; rows.len()];
+        let mut r = rhs;
+        let mut z = r.iter().zip(degree).map(|(r, d)| r / d).collect::<Vec<_>>();
+        let mut p = z.clone();
+        let mut rz = dot(&r, &z);
+        for _ in 0..SOLVER_STEPS {
+            if dot(&r, &r) <= tolerance {
+                break;
+            }
+            let ap = apply(&p);
+            let alpha = rz / dot(&p, &ap);
+            for i in 0..x.len() {
+                x[i] += alpha * p[i];
+                r[i] -= alpha * ap[i];
+            }
+            z = r.iter().zip(degree).map(|(r, d)| r / d).collect();
+            let rz_next = dot(&r, &z);
+            let beta = rz_next / rz;
+            rz = rz_next;
+            for i in 0..p.len() {
+                p[i] = z[i] + beta * p[i];
+            }
+        }
+
+        x
+    }
+
+    // Möbius transformations moving the area centroid of the vertexes to the
+    // origin, so that they do not crowd on a side of the sphere
+    fn balance(&self, sphere: &mut [Vertex]) {
+        let total = self.areas.iter().sum::<f64>();
+        for _ in 0..BALANCE_STEPS {
+            let centroid = sphere
+                .iter()
+                .zip(&self.areas)
+                .fold(Vertex::new(0., 0., 0.), |sum, (&x, &area)| sum + x * area)
+                / total;
+            if centroid.len() < 1e-9 {
+                break;
+            }
+            for (x, nbrs) in sphere.iter_mut().zip(&self.neighbors) {
+                if !nbrs.is_empty() {
+                    *x = Self::mobius(*x, centroid);
+                }
+            }
+        }
+    }
+
+    // Large thin faces may turn over on the sphere, as the lifted edges are
+    // circles and not great circles. Their vertexes are moved to the mean of
+    // their neighbours until the faces keep the orientation of the flat embedding.
+    // A face still turned over after `UNTANGLE_STEPS` is reported.
+    fn untangle(
+        &self,
+        object: &Object,
+        plane: &[Vertex],
+        sphere: &mut [Vertex],
+    ) -> Result<(), MeshErrorKind> {
+        // the first face is the outer one in the plane, so its orientation is reversed
+        let orientations = (0..object.nfaces())
+            .map(|ind| {
+                let face = object.face_indexes(ind);
+                let (a, b, c) = (plane[face[0].0], plane[face[1].0], plane[face[2].0]);
+                let area = ((b - a) ^ (c - a)).z;
+                if ind == 0 {
+                    -area.signum()
+                } else {
+                    area.signum()
+                }
+            })
+            .collect::<Vec<_>>();
+        let volume = |sphere: &[Vertex], ind: usize| {
+            let face = object.face_indexes(ind);
+            sphere[face[0].0] * (sphere[face[1].0] ^ sphere[face[2].0]) * orientations[ind]
+        };
+        let sign = (0..object.nfaces())
+            .map(|ind| volume(sphere, ind).signum())
+            .sum::<f64>()
+            .signum();
+
+        let is_flipped = |sphere: &[Vertex], ind: usize| volume(sphere, ind) * sign <= 0.;
+
+        for _ in 0..UNTANGLE_STEPS {
+            let flipped = (0..object.nfaces())
+                .filter(|&ind| is_flipped(sphere, ind))
+                .flat_map(|ind| object.face_indexes(ind))
+                .map(|(v, _)| v)
+                .collect::<BTreeSet<_>>();
+            if flipped.is_empty() {
+                return Ok(());
+            }
+            for v in flipped {
+                let mut mean = self.neighbors[v]
+                    .iter()
+                    .fold(Vertex::new(0., 0., 0.), |sum, &u| sum + sphere[u]);
+                if mean.len() > EPS {
+                    mean.normalize();
+                    sphere[v] = mean;
+                }
+            }
+        }
+
+        match (0..object.nfaces()).find(|&ind| is_flipped(sphere, ind)) {
+            Some(face) => Err(MeshErrorKind::Fold(face)),
+            None => Ok(()),
+        }
+    }
+
+    // Transformation of the unit ball taking `a` to the origin
+    fn mobius(x: Vertex, a: Vertex) -> Vertex {
+        let d = x - a;
+        let d2 = d.len2();
+        let mut res = (d * (1. - a.len2()) - a * d2) / d2;
+        res.normalize();
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn load(path: &str) -> Object {
+        Object::load(path, Color::new([255; 4])).unwrap()
+    }
+
+    #[test]
+    fn cube_embeds_without_folds() {
+        let cube = load("./models/cube.obj");
+        let sphere = HarmonicMap::new(&cube).unwrap().embed(&cube, 2.).unwrap();
+
+        assert_eq!(sphere.len(), cube.nvertexes());
+        assert!(sphere.iter().all(|v| (v.len() - 2.).abs() < 1e-6));
+    }
+
+    #[test]
+    fn open_model_has_boundary() {
+        let head = load("./models/head.obj");
+
+        assert!(matches!(
+            HarmonicMap::new(&head),
+            Err(MeshErrorKind::Boundary(..))
+        ));
+    }
+
+    #[test]
+    fn two_spheres_are_disconnected() {
+        let corners = [(0., 0., 0.), (1., 0., 0.), (0., 1., 0.), (0., 0., 1.)];
+        let vertexes = [0., 5.]
+            .iter()
+            .flat_map(|&shift| {
+                corners
+                    .iter()
+                    .map(move |&(x, y, z)| Vertex::new(x + shift, y, z))
+            })
+            .collect();
+        let faces = [0, 4]
+            .iter()
+            .flat_map(|&k| {
+                [[0, 2, 1], [0, 1, 3], [1, 2, 3], [0, 3, 2]]
+                    .map(|face| face.iter().map(|&v| (v + k, 0)).collect::<Vec<_>>())
+            })
+            .collect();
+        let tetrahedra = Object::new(
+            vertexes,
+            faces,
+            vec![Vertex::new(0., 0., 1.)],
+            Color::new([255; 4]),
+        );
+
+        assert!(matches!(
+            HarmonicMap::new(&tetrahedra),
+            Err(MeshErrorKind::Disconnected(4))
+        ));
+    }
+}
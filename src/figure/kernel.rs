@@ -0,0 +1,252 @@
+use super::{object::Object, triangle::Triangle, vertex::Vertex};
+use crate::EPS;
+
+const SEARCH_STEPS: usize = 1000;
+const SIMPLEX_STEPS: usize = 10000;
+
+// Kernel of a polyhedron: points lying inside the planes of all its faces,
+// from which the whole surface is seen. It is empty if the model is not star-shaped.
+pub struct Kernel {
+    planes: Vec<(Vertex, f64)>, // (outward unit normal, offset), inside is `n * p <= offset`
+    size: f64,
+}
+
+impl Kernel {
+    pub fn new(object: &Object) -> Self {
+        let planes = (0..object.nfaces())
+            .map(|ind| {
+                let normal = Self::outward_normal(object, ind);
+                (normal, normal * object.face_coords(ind)[0])
+            })
+            .collect();
+        let center = *object.center();
+        let size = object
+            .vertexes_iter()
+            .map(|&v| (v - center).len())
+            .fold(0., f64::max);
+
+        Self { planes, size }
+    }
+
+    pub fn normal(&self, face: usize) -> Vertex {
+        self.planes[face].0
+    }
+
+    // Starting from `start`, the point is moved onto the plane it violates
+    // most until it is strictly inside all of them
+    pub fn find_point(&self, start: Vertex) -> Option<Vertex> {
+        let margin = 1e-4 * self.size;
+        let mut point = start;
+        for _ in 0..SEARCH_STEPS {
+            let (violation, normal) = self
+                .planes
+                .iter()
+                .map(|&(n, offset)| (n * point - offset + margin, n))
+                .max_by(|a, b| a.0.total_cmp(&b.0))?;
+            if violation <= 0. {
+                return Some(point);
+            }
+            point -= normal * violation;
+        }
+
+        None
+    }
+
+    // Center of the largest ball inside the kernel and its radius
+    pub fn chebyshev_center(&self, start: Vertex) -> Option<(Vertex, f64)> {
+        let origin = self.find_point(start)?;
+        // max r: n * (q+ - q-) + r <= offset - n * origin, with q+, q-, r >= 0.
+        // `origin` is strictly inside, so zero is a feasible start.
+        let rows = self
+            .planes
+            .iter()
+            .map(|&(n, offset)| ([n.x, n.y, n.z, -n.x, -n.y, -n.z, 1.], offset - n * origin))
+            .collect();
+        let x = Simplex::new(rows, [0., 0., 0., 0., 0., 0., 1.]).maximize()?;
+        let q = Vertex::new(x[0] - x[3], x[1] - x[4], x[2] - x[5]);
+
+        Some((origin + q, x[6]))
+    }
+}
+
+impl Kernel {
+    // Winding may be inconsistent in OBJ files, so the side is taken from the normals
+    fn outward_normal(object: &Object, face: usize) -> Vertex {
+        let coords = object.face_coords(face);
+        let mut normal = Triangle::new(coords[0], coords[1], coords[2]).normal();
+        let vertex_normals = object.face_normals(face);
+        if normal * (vertex_normals[0] + vertex_normals[1] + vertex_normals[2]) < 0. {
+            normal = -normal;
+        }
+        if normal.len() > EPS {
+            normal.normalize();
+        }
+
+        normal
+    }
+}
+
+const N: usize = 7;
+
+// Dictionary simplex method for `max c * x` with `a * x <= b`, `x >= 0` and `b >= 0`.
+// Each row keeps a basic variable as `b - a * nonbasic`, Bland's rule prevents cycling.
+struct Simplex {
+    rows: Vec<([f64; N], f64)>,
+    basic: Vec<usize>,
+    nonbasic: [usize; N],
+    objective: [f64; N],
+}
+
+impl Simplex {
+    fn new(rows: Vec<([f64; N], f64)>, objective: [f64; N]) -> Self {
+        let basic = (N..N + rows.len()).collect();
+        let mut nonbasic = [0; N];
+        for (j, var) in nonbasic.iter_mut().enumerate() {
+            *var = j;
+        }
+
+        Self {
+            rows,
+            basic,
+            nonbasic,
+            objective,
+        }
+    }
+
+    // `None` if the objective is unbounded or the optimum is not reached in `SIMPLEX_STEPS`
+    fn maximize(mut self) -> Option<[f64; N]> {
+        for _ in 0..SIMPLEX_STEPS {
+            let Some(e) = (0..N)
+                .filter(|&j| self.objective[j] > EPS)
+                .min_by_key(|&j| self.nonbasic[j])
+            else {
+                return Some(self.solution());
+            };
+            let l = (0..self.rows.len())
+                .filter(|&i| self.rows[i].0[e] > EPS)
+                .min_by(|&i, &k| {
+                    let ratio = |i: usize| self.rows[i].1 / self.rows[i].0[e];
+                    ratio(i)
+                        .total_cmp(&ratio(k))
+                        .then(self.basic[i].cmp(&self.basic[k]))
+                })?;
+            self.pivot(l, e);
+        }
+
+        None
+    }
+
+    fn solution(&self) -> [f64; N] {
+        let mut x = [0.; N];
+        for (i, &var) in self.basic.iter().enumerate() {
+            if var < N {
+                x[var] = self.rows[i].1.max(0.);
+            }
+        }
+
+        x
+    }
+
+    fn pivot(&mut self, l: usize, e: usize) {
+        let (mut row, b) = self.rows[l];
+        let pivot = row[e];
+        for (j, a) in row.iter_mut().enumerate() {
+            *a = if j == e { 1. / pivot } else { *a / pivot };
+        }
+        let b = b / pivot;
+        self.rows[l] = (row, b);
+
+        for (i, (a, b_i)) in self.rows.iter_mut().enumerate() {
+            if i == l || a[e] == 0. {
+                continue;
+            }
+            let k = a[e];
+            for j in 0..N {
+                a[j] = if j == e {
+                    -k * row[e]
+                } else {
+                    a[j] - k * row[j]
+                };
+            }
+            *b_i = (*b_i - k * b).max(0.);
+        }
+
+        let k = self.objective[e];
+        for j in 0..N {
+            self.objective[j] = if j == e {
+                -k * row[e]
+            } else {
+                self.objective[j] - k * row[j]
+            };
+        }
+
+        std::mem::swap(&mut self.basic[l], &mut self.nonbasic[e]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    use std::fs;
+
+    #[test]
+    fn cube_center() {
+        let cube = Object::load("./models/cube.obj", Color::new([255; 4])).unwrap();
+        let (center, radius) = Kernel::new(&cube)
+            .chebyshev_center(Vertex::new(0.5, 0.2, -0.3))
+            .unwrap();
+
+        assert!(center.len() < 1e-6);
+        assert!((radius - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn u_shape_has_empty_kernel() {
+        // the inner sides of the prongs need x <= 1 and x >= 2 at once
+        let outline = [
+            (0, 0),
+            (3, 0),
+            (3, 3),
+            (2, 3),
+            (2, 1),
+            (1, 1),
+            (1, 3),
+            (0, 3),
+        ];
+        let cap = [
+            (0, 1, 4),
+            (1, 2, 3),
+            (1, 3, 4),
+            (0, 4, 5),
+            (0, 5, 7),
+            (5, 6, 7),
+        ];
+        let mut text = String::new();
+        for z in [0, 1] {
+            for (x, y) in outline {
+                text += &format!("v {x} {y} {z}\n");
+            }
+        }
+        for (a, b, c) in cap {
+            text += &format!("f {} {} {}\n", a + 1, c + 1, b + 1);
+            text += &format!("f {} {} {}\n", a + 9, b + 9, c + 9);
+        }
+        for i in 0..8 {
+            let j = (i + 1) % 8;
+            text += &format!("f {} {} {}\n", i + 1, j + 1, j + 9);
+            text += &format!("f {} {} {}\n", i + 1, j + 9, i + 9);
+        }
+        let dir = std::env::temp_dir().join(format!("morphing_kernel_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("u.obj");
+        fs::write(&path, text).unwrap();
+        let object = Object::load(&path.to_string_lossy(), Color::new([255; 4])).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let kernel = Kernel::new(&object);
+        assert!(kernel.find_point(*object.center()).is_none());
+        assert!(kernel.chebyshev_center(*object.center()).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::color::Color;
+use crate::texture::SharedTexture;
+
+#[derive(Clone)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Color,                 // Ka
+    pub diffuse: Color,                 // Kd, alpha is taken from `d`
+    pub specular: Color,                // Ks
+    pub shininess: f64,                 // Ns
+    pub texture: Option<SharedTexture>, // map_Kd
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ambient: Color::new([0, 0, 0, 255]),
+            diffuse: Color::new([255; 4]),
+            specular: Color::new([0, 0, 0, 255]),
+            shininess: 0.,
+            texture: None,
+        }
+    }
+
+    pub fn from_color(color: Color) -> Self {
+        let mut material = Self::new("");
+        material.diffuse = color;
+        material
+    }
+
+    pub fn interpolation(m1: &Material, m2: &Material, ratio: f64) -> Material {
+        let name = if m1.name == m2.name {
+            m1.name.clone()
+        } else {
+            format!("{}_{}", m1.name, m2.name)
+        };
+        // Different textures can not be blended, so the nearest endpoint wins
+        let texture = match (&m1.texture, &m2.texture) {
+            (Some(t1), Some(t2)) => Some(if ratio < 0.5 { t1 } else { t2 }.clone()),
+            (Some(t), None) | (None, Some(t)) => Some(t.clone()),
+            (None, None) => None,
+        };
+
+        Material {
+            name,
+            ambient: Color::interpolation(m1.ambient.clone(), m2.ambient.clone(), ratio),
+            diffuse: Color::interpolation(m1.diffuse.clone(), m2.diffuse.clone(), ratio),
+            specular: Color::interpolation(m1.specular.clone(), m2.specular.clone(), ratio),
+            shininess: m1.shininess + (m2.shininess - m1.shininess) * ratio,
+            texture,
+        }
+    }
+}
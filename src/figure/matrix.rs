@@ -0,0 +1,178 @@
+use super::vertex::Vertex;
+use crate::EPS;
+
+const JACOBI_SWEEPS: usize = 50;
+
+pub type Matrix = [[f64; 3]; 3]; // rows
+
+pub const IDENTITY: Matrix = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+
+pub fn transform(m: &Matrix, v: Vertex) -> Vertex {
+    let [x, y, z] = m.map(|row| row[0] * v.x + row[1] * v.y + row[2] * v.z);
+
+    Vertex::new(x, y, z)
+}
+
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    a.map(|row| [0, 1, 2].map(|j| row[0] * b[0][j] + row[1] * b[1][j] + row[2] * b[2][j]))
+}
+
+// Adds `a * b^T` to `m`
+pub fn add_outer(m: &mut Matrix, a: Vertex, b: Vertex) {
+    for (row, a) in m.iter_mut().zip([a.x, a.y, a.z]) {
+        for (m, b) in row.iter_mut().zip([b.x, b.y, b.z]) {
+            *m += a * b;
+        }
+    }
+}
+
+// Shortest rotation taking the direction `a` to `b`
+pub fn rotation_between(a: Vertex, b: Vertex) -> Matrix {
+    let (a, b) = (a / a.len(), b / b.len());
+    let axis = a ^ b;
+    let (sin, cos) = (axis.len(), a * b);
+    if sin < EPS {
+        if cos > 0. {
+            return IDENTITY;
+        }
+        // half turn around any axis orthogonal to `a`
+        let mut axis = if a.x.abs() < 0.9 {
+            Vertex::new(1., 0., 0.) ^ a
+        } else {
+            Vertex::new(0., 1., 0.) ^ a
+        };
+        axis.normalize();
+        return rodrigues(axis, 0., -1.);
+    }
+
+    rodrigues(axis / sin, sin, cos)
+}
+
+// Rotation by `angle` around the unit `axis`
+pub fn axis_rotation(axis: Vertex, angle: f64) -> Matrix {
+    let (sin, cos) = angle.sin_cos();
+
+    rodrigues(axis, sin, cos)
+}
+
+// Rotation around the same axis as `r` by the part `t` of its angle
+pub fn rotation_power(r: &Matrix, t: f64) -> Matrix {
+    let cos = ((r[0][0] + r[1][1] + r[2][2] - 1.) / 2.).clamp(-1., 1.);
+    // the skew-symmetric part is the axis times twice the sine
+    let skew = Vertex::new(r[2][1] - r[1][2], r[0][2] - r[2][0], r[1][0] - r[0][1]);
+    let axis = if skew.len() > 1e-6 {
+        skew / skew.len()
+    } else if cos > 0. {
+        return IDENTITY;
+    } else {
+        // a half turn is `2 * k * k^T - I`, its column with the largest diagonal is the safest
+        let i = (0..3)
+            .max_by(|&i, &j| r[i][i].total_cmp(&r[j][j]))
+            .unwrap_or(0);
+        let [x, y, z] = [0, 1, 2].map(|j| r[j][i] + IDENTITY[j][i]);
+        let mut k = Vertex::new(x, y, z);
+        k.normalize();
+        k
+    };
+
+    axis_rotation(axis, cos.acos() * t)
+}
+
+pub fn transpose(m: &Matrix) -> Matrix {
+    [0, 1, 2].map(|i| [0, 1, 2].map(|j| m[j][i]))
+}
+
+// `None` for a singular matrix
+pub fn inverse(m: &Matrix) -> Option<Matrix> {
+    let [a, b, c] = m.map(|row| Vertex::new(row[0], row[1], row[2]));
+    let det = a * (b ^ c);
+    if det.abs() < EPS {
+        return None;
+    }
+    // the columns of the inverse are the cross products of the rows
+    let columns = [b ^ c, c ^ a, a ^ b].map(|v| v / det);
+
+    Some(transpose(&columns.map(|v| [v.x, v.y, v.z])))
+}
+
+fn rodrigues(k: Vertex, sin: f64, cos: f64) -> Matrix {
+    let k = [k.x, k.y, k.z];
+    let cross = [[0., -k[2], k[1]], [k[2], 0., -k[0]], [-k[1], k[0], 0.]];
+
+    [0, 1, 2].map(|i| {
+        [0, 1, 2].map(|j| f64::from(i == j) * cos + cross[i][j] * sin + k[i] * k[j] * (1. - cos))
+    })
+}
+
+// Orthogonal matrix closest to `m`, that is the rotation best taking the
+// vectors `b` to `a` for `m = sum(a * b^T)`. A reflection is allowed if it fits
+// better. Built from the eigenvectors of `m^T * m`, so that a degenerate `m`
+// still gives a rotation.
+pub fn orthogonal_fit(m: Matrix, allow_reflection: bool) -> Matrix {
+    let mut a = [[0.; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            a[i][j] = (0..3).map(|k| m[k][i] * m[k][j]).sum();
+        }
+    }
+    let mut v = IDENTITY;
+    for _ in 0..JACOBI_SWEEPS {
+        let off = a[0][1].abs() + a[0][2].abs() + a[1][2].abs();
+        if off <= 1e-15 * (a[0][0].abs() + a[1][1].abs() + a[2][2].abs()) {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0. {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+            let c = 1. / (t * t + 1.).sqrt();
+            let s = t * c;
+            for row in a.iter_mut() {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (ap, aq) = (a[p], a[q]);
+            a[p] = [0, 1, 2].map(|k| c * ap[k] - s * aq[k]);
+            a[q] = [0, 1, 2].map(|k| s * ap[k] + c * aq[k]);
+            for row in v.iter_mut() {
+                let (vp, vq) = (row[p], row[q]);
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+
+    // right singular vectors by decreasing singular value and the left ones matching them
+    let mut order = [0, 1, 2];
+    order.sort_by(|&i, &j| a[j][j].total_cmp(&a[i][i]));
+    let right = order.map(|i| Vertex::new(v[0][i], v[1][i], v[2][i]));
+    let mut left = right.map(|r| transform(&m, r));
+    let scale = left[0].len();
+    if scale < EPS {
+        return IDENTITY;
+    }
+    left[0].normalize();
+    // a single direction is matched by the shortest rotation
+    if left[1].len() < 1e-6 * scale {
+        left[1] = transform(&rotation_between(right[0], left[0]), right[1]);
+    }
+    left[1].normalize();
+    // the last pair decides between a rotation and a reflection, a weak one is dropped
+    let is_reflection_fit = allow_reflection && left[2].len() > 1e-6 * scale;
+    let sign = if is_reflection_fit {
+        (left[2] * (left[0] ^ left[1])).signum()
+    } else {
+        (right[2] * (right[0] ^ right[1])).signum()
+    };
+    left[2] = (left[0] ^ left[1]) * sign;
+
+    let mut q = [[0.; 3]; 3];
+    for (&u, &r) in left.iter().zip(right.iter()) {
+        add_outer(&mut q, u, r);
+    }
+
+    q
+}
@@ -1,278 +1,394 @@
-use crate::{color::Color, EPS};
+use crate::{color::Color, parallel, texture::SharedTexture, EPS};
 
 use super::{
-    arc::{Arc, ArcIntersectionResult},
-    edge::Edge,
-    edge_set::EdgeSet,
-    graph::{Graph, RcGraphEdge},
+    gltf::MorphMesh,
+    material::Material,
+    morph_error::{Mesh, MorphError},
     object::Object,
+    overlay::Overlay,
+    progress::{Phase, Progress},
     projection::Projection,
-    vertex::Vertex,
+    ratio_mask::RatioMask,
+    trajectory::{RigidPath, Trajectory},
+    vertex::{TexCoord, Vertex},
 };
 
-use std::cmp::Ordering;
-use std::f64::consts::PI;
+use std::collections::BTreeMap;
+
+type MaterialPairs = Vec<(Material, Material)>;
 
 pub struct MergedObject {
     faces: Vec<Vec<usize>>,
     vertexes_pairs: Vec<(Vertex, Vertex)>,
     normals_pairs: Vec<(Vertex, Vertex)>,
+    uv_pairs: Vec<(TexCoord, TexCoord)>, // empty if neither object has UVs
+    colors_pairs: Vec<(Color, Color)>,   // empty if neither object has vertex colours
+    mask_pairs: Vec<(f64, f64)>,         // painted weights, empty if neither object is painted
+    material_pairs: MaterialPairs,       // empty if neither object has materials
+    face_materials: Vec<usize>,          // index in `material_pairs` for each face
     color_pairs: (Color, Color),
-}
-
-struct SphereVertex {
-    vertex: Vertex,
-    index: usize,
-    origin_id: usize,
+    texture_pairs: (Option<SharedTexture>, Option<SharedTexture>),
+    trajectory: Trajectory,
+    rigid_path: Option<RigidPath>, // built for every link by the app worker, lazily by `set_trajectory` in the CLI
+    ratio_mask: RatioMask,
+    mask_weights: Vec<f64>, // of each vertex in `ratio_mask`, empty if it is uniform
+    is_mask_reversed: bool, // the mask is laid on the target, which the morph starts from
 }
 
 impl MergedObject {
-    pub fn new(src_proj: Projection, dst_proj: Projection) -> Result<Self, ()> {
-        let n = src_proj.nvertexes();
-        let m = dst_proj.nvertexes();
-        let mut sphere_vertexes = Vec::with_capacity(n + m);
-        let mut edges = EdgeSet::new();
-
-        for i in 0..n {
-            sphere_vertexes.push(SphereVertex {
-                vertex: src_proj.sphere_vertex(i),
-                index: i,
-                origin_id: 1,
-            });
-        }
-        for i in 0..m {
-            sphere_vertexes.push(SphereVertex {
-                vertex: dst_proj.sphere_vertex(i),
-                index: i,
-                origin_id: 2,
-            });
-        }
+    pub fn new(src_proj: Projection, dst_proj: Projection) -> Result<Self, MorphError> {
+        Self::with_progress(src_proj, dst_proj, &Progress::new())
+    }
 
-        for src_edge in src_proj.edges_iter() {
-            edges.insert(src_edge);
-        }
-        for dst_edge in dst_proj.edges_iter() {
-            let dst_edge = Edge::new(dst_edge.from + n, dst_edge.to + n);
-            let v1 = sphere_vertexes[dst_edge.from].vertex;
-            let v2 = sphere_vertexes[dst_edge.to].vertex;
-            let dst_arc = Arc::new(v1, v2, dst_edge.from, dst_edge.to);
-            let mut intersections = vec![(0., dst_edge.from), (1., dst_edge.to)];
-            let mut is_skip_add = false;
-            for src_edge in edges.clone().iter() {
-                let u1 = sphere_vertexes[src_edge.from].vertex;
-                let u2 = sphere_vertexes[src_edge.to].vertex;
-                let src_arc = Arc::new(u1, u2, src_edge.from, src_edge.to);
-                match Arc::intersect(&src_arc, &dst_arc) {
-                    ArcIntersectionResult::T1(index, k) => intersections.push((k, index)),
-                    ArcIntersectionResult::T2(index, ..) => {
-                        edges.remove(src_edge);
-                        edges.add(src_edge.from, index);
-                        edges.add(src_edge.to, index);
-                    }
-                    ArcIntersectionResult::X(vertex, k) => {
-                        let id = sphere_vertexes.len();
-                        sphere_vertexes.push(SphereVertex {
-                            vertex,
-                            origin_id: 0,
-                            index: 0,
-                        });
-                        edges.remove(src_edge);
-                        edges.add(src_edge.from, id);
-                        edges.add(src_edge.to, id);
-                        intersections.push((k, id));
-                    }
-                    ArcIntersectionResult::I((id1, k1), (id2, k2)) => {
-                        edges.remove(src_edge);
-                        if k1 > 0. {
-                            intersections.push((k1, id1));
-                        } else if k1 < 0. {
-                            edges.add(id1, intersections[0].1);
-                        }
+    // Reports each phase to `progress` and gives up with an error once it is cancelled
+    pub fn with_progress(
+        src_proj: Projection,
+        dst_proj: Projection,
+        progress: &Progress,
+    ) -> Result<Self, MorphError> {
+        let in_src = |kind| Mesh::Source.error(kind);
+        let in_dst = |kind| Mesh::Target.error(kind);
+        src_proj.check().map_err(in_src)?;
+        dst_proj.check().map_err(in_dst)?;
 
-                        if k2 < 1. {
-                            intersections.push((k2, id2));
-                        } else if k2 > 1. {
-                            edges.add(id2, intersections[1].1);
-                        }
-                    }
-                    ArcIntersectionResult::L(id1, id2) => {
-                        if id2 == dst_edge.from {
-                            intersections[0].1 = id1;
-                        } else if id2 == dst_edge.to {
-                            intersections[1].1 = id1;
-                        }
-                    }
-                    ArcIntersectionResult::S => {
-                        is_skip_add = true;
-                        break;
-                    }
-                    ArcIntersectionResult::N => {}
-                }
-            }
-            if is_skip_add {
-                continue;
-            }
+        let overlay = Overlay::new(&[&src_proj, &dst_proj], progress)?;
+        let sphere_vertexes = overlay.vertexes();
 
-            intersections.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
-            for i in 0..intersections.len() - 1 {
-                edges.add(intersections[i].1, intersections[i + 1].1);
+        progress.start(Phase::BackProjection, sphere_vertexes.len());
+        let pairs = parallel::try_map(sphere_vertexes, |vertex| {
+            if progress.is_cancelled() {
+                return Err(MorphError::Cancelled);
             }
-        }
-
-        let mut vertexes_pairs = Vec::new();
-        let mut normals_pairs = Vec::new();
-        let mut vp = (Vertex::default(), Vertex::default());
-        let mut np = (Vertex::default(), Vertex::default());
-        for vertex in sphere_vertexes.iter() {
-            match vertex.origin_id {
-                1 => {
-                    let dst = dst_proj.project_from_sphere(vertex.vertex)?;
-                    vp = (src_proj.vertex(vertex.index), dst.0);
-                    np = (src_proj.normal(vertex.index), dst.1);
+            progress.advance(1);
+            let (mut vp, np, tp, cp, mp) = match vertex.origin {
+                Some((0, index)) => {
+                    let dst = dst_proj
+                        .project_from_sphere(vertex.vertex)
+                        .map_err(in_dst)?;
+                    (
+                        (src_proj.vertex(index), dst.vertex),
+                        (src_proj.normal(index), dst.normal),
+                        (src_proj.texcoord(index), dst.texcoord),
+                        (src_proj.vertex_color(index), dst.color),
+                        (src_proj.mask_weight(index), dst.mask),
+                    )
                 }
-                2 => {
-                    let src = src_proj.project_from_sphere(vertex.vertex)?;
-                    vp = (src.0, dst_proj.vertex(vertex.index));
-                    np = (src.1, dst_proj.normal(vertex.index));
+                Some((_, index)) => {
+                    let src = src_proj
+                        .project_from_sphere(vertex.vertex)
+                        .map_err(in_src)?;
+                    (
+                        (src.vertex, dst_proj.vertex(index)),
+                        (src.normal, dst_proj.normal(index)),
+                        (src.texcoord, dst_proj.texcoord(index)),
+                        (src.color, dst_proj.vertex_color(index)),
+                        (src.mask, dst_proj.mask_weight(index)),
+                    )
                 }
-                _ => {
-                    let src = src_proj.project_from_sphere(vertex.vertex)?;
-                    let dst = dst_proj.project_from_sphere(vertex.vertex)?;
-                    vp = (src.0, dst.0);
-                    np = (src.1, dst.1);
+                None => {
+                    let src = src_proj
+                        .project_from_sphere(vertex.vertex)
+                        .map_err(in_src)?;
+                    let dst = dst_proj
+                        .project_from_sphere(vertex.vertex)
+                        .map_err(in_dst)?;
+                    (
+                        (src.vertex, dst.vertex),
+                        (src.normal, dst.normal),
+                        (src.texcoord, dst.texcoord),
+                        (src.color, dst.color),
+                        (src.mask, dst.mask),
+                    )
                 }
             };
             vp.0 -= *src_proj.center();
             vp.1 -= *dst_proj.center();
+
+            Ok((vp, np, tp, cp, mp))
+        })?;
+        let mut vertexes_pairs = Vec::with_capacity(pairs.len());
+        let mut normals_pairs = Vec::with_capacity(pairs.len());
+        let mut texcoords_pairs = Vec::with_capacity(pairs.len());
+        let mut colors_pairs = Vec::with_capacity(pairs.len());
+        let mut mask_pairs = Vec::with_capacity(pairs.len());
+        for (vp, np, tp, cp, mp) in pairs.into_iter() {
             vertexes_pairs.push(vp);
             normals_pairs.push(np);
+            texcoords_pairs.push(tp);
+            colors_pairs.push(cp);
+            mask_pairs.push(mp);
         }
+        // without vertex colours on both sides the uniform colour is morphed as before
+        if !src_proj.has_vertex_colors() && !dst_proj.has_vertex_colors() {
+            colors_pairs.clear();
+        }
+        if !src_proj.has_mask() && !dst_proj.has_mask() {
+            mask_pairs.clear();
+        }
+        let uv_pairs = Self::resolve_uv_pairs(texcoords_pairs);
 
-        let sphere_vertexes = sphere_vertexes.iter().map(|v| v.vertex).collect();
-        let faces = Self::resolve_faces(&sphere_vertexes, &edges);
-
-        let mut triangle_faces = Vec::new();
-        let mut set = std::collections::BTreeSet::<Vec<usize>>::new();
-        for f in faces.into_iter() {
-            if f.len() > 3 {
-                for i in 1..f.len() - 1 {
-                    let mut tri = vec![f[0], f[i], f[i + 1]];
-                    tri.sort();
-                    if f[0] == f[i] || f[0] == f[i + 1] {
-                        continue;
-                    }
-                    if set.insert(tri.clone()) {
-                        triangle_faces.push(tri);
-                    }
-                }
-            } else {
-                let mut tri = f;
-                tri.sort();
-                if set.insert(tri.clone()) {
-                    triangle_faces.push(tri);
-                }
-            }
+        let sphere_vertexes: Vec<Vertex> = sphere_vertexes.iter().map(|v| v.vertex).collect();
+        progress.start(Phase::FaceResolution, 2);
+        if progress.is_cancelled() {
+            return Err(MorphError::Cancelled);
         }
+        let triangle_faces = overlay.triangles().map_err(|ind| MorphError::Overlay {
+            mesh: Mesh::Source,
+            vertex: src_proj.nearest_vertex(sphere_vertexes[ind]),
+        })?;
+        progress.advance(1);
+
+        let (material_pairs, face_materials) =
+            Self::resolve_materials(&src_proj, &dst_proj, &sphere_vertexes, &triangle_faces)?;
+        progress.advance(1);
 
         Ok(Self {
             vertexes_pairs,
             normals_pairs,
+            uv_pairs,
+            colors_pairs,
+            material_pairs,
+            face_materials,
             faces: triangle_faces,
             color_pairs: (src_proj.color().clone(), dst_proj.color().clone()),
+            texture_pairs: (src_proj.texture(), dst_proj.texture()),
+            mask_pairs,
+            trajectory: Trajectory::default(),
+            rigid_path: None,
+            ratio_mask: RatioMask::default(),
+            mask_weights: Vec::new(),
+            is_mask_reversed: false,
         })
     }
 
+    pub fn trajectory(&self) -> Trajectory {
+        self.trajectory
+    }
+
+    pub fn set_trajectory(&mut self, trajectory: Trajectory) {
+        if trajectory == Trajectory::Rigid {
+            self.build_rigid_path();
+        }
+        self.trajectory = trajectory;
+    }
+
+    // The rigid trajectory takes a while to prepare, so the app builds it
+    // with the morph, before it is chosen
+    pub fn build_rigid_path(&mut self) {
+        if self.rigid_path.is_none() {
+            self.rigid_path = Some(RigidPath::new(&self.vertexes_pairs, &self.faces));
+        }
+    }
+
+    // The weights of the vertexes are found where the morph starts, on the
+    // target if `is_reversed`
+    pub fn set_mask(&mut self, mask: RatioMask, is_reversed: bool) {
+        self.mask_weights = if mask.is_uniform() {
+            Vec::new()
+        } else {
+            parallel::map_range(self.vertexes_pairs.len(), |i| {
+                let (v1, v2) = self.vertexes_pairs[i];
+                let (m1, m2) = self.mask_pairs.get(i).copied().unwrap_or((1., 1.));
+                if is_reversed {
+                    mask.weight(v2, m2)
+                } else {
+                    mask.weight(v1, m1)
+                }
+            })
+        };
+        self.ratio_mask = mask;
+        self.is_mask_reversed = is_reversed;
+    }
+
+    // Under a mask each vertex, with its normal, UV and colour, makes its own
+    // share of the way, the colour, materials and texture of the whole object
+    // follow `ratio`
     pub fn interpolation(&self, ratio: f64) -> Object {
-        let vertexes = self
-            .vertexes_pairs
-            .iter()
-            .map(|(v1, v2)| *v1 + (*v2 - *v1) * ratio)
-            .collect();
-        let normals = self
-            .normals_pairs
-            .iter()
-            .map(|(v1, v2)| *v1 + (*v2 - *v1) * ratio)
-            .collect();
+        let ratios = self.local_ratios(ratio);
+        let vertexes = match (self.trajectory, &self.rigid_path) {
+            (Trajectory::Spherical, _) => parallel::map_range(ratios.len(), |i| {
+                let (v1, v2) = self.vertexes_pairs[i];
+                v1.slerp(v2, ratios[i])
+            }),
+            (Trajectory::Rigid, Some(path)) => path.interpolation(&self.vertexes_pairs, &ratios),
+            _ => parallel::map_range(ratios.len(), |i| {
+                let (v1, v2) = self.vertexes_pairs[i];
+                v1 + (v2 - v1) * ratios[i]
+            }),
+        };
+        // the normals turn with the faces unless they move straight
+        let normals = match self.trajectory {
+            Trajectory::Linear => parallel::map_range(ratios.len(), |i| {
+                let (v1, v2) = self.normals_pairs[i];
+                v1 + (v2 - v1) * ratios[i]
+            }),
+            _ => parallel::map_range(ratios.len(), |i| {
+                let (v1, v2) = self.normals_pairs[i];
+                v1.slerp(v2, ratios[i])
+            }),
+        };
         let faces = self
             .faces
             .iter()
             .map(|f| f.iter().map(|&v| (v, v)).collect())
             .collect();
+        let texcoords = parallel::map_range(self.uv_pairs.len(), |i| {
+            let ((u1, v1), (u2, v2)) = self.uv_pairs[i];
+            (u1 + (u2 - u1) * ratios[i], v1 + (v2 - v1) * ratios[i])
+        });
+        let face_texcoords = if self.uv_pairs.is_empty() {
+            Vec::new()
+        } else {
+            self.faces.clone()
+        };
         let color = Color::interpolation(
             self.color_pairs.0.clone(),
             self.color_pairs.1.clone(),
             ratio,
         );
 
-        Object::new(vertexes, faces, normals, color)
+        // Textures can not be blended pixel by pixel since their UV layouts differ,
+        // so the frame takes the texture of the nearest endpoint
+        let texture = match &self.texture_pairs {
+            (Some(t1), Some(t2)) => Some(if ratio < 0.5 { t1 } else { t2 }.clone()),
+            (Some(t), None) | (None, Some(t)) => Some(t.clone()),
+            (None, None) => None,
+        };
+
+        let mut object =
+            Object::new(vertexes, faces, normals, color).with_texcoords(texcoords, face_texcoords);
+        object.set_texture(texture);
+        if !self.material_pairs.is_empty() {
+            let materials = self
+                .material_pairs
+                .iter()
+                .map(|(m1, m2)| Material::interpolation(m1, m2, ratio))
+                .collect();
+            let face_materials = self.face_materials.iter().map(|&m| Some(m)).collect();
+            object = object.with_materials(materials, face_materials);
+        }
+        if !self.colors_pairs.is_empty() {
+            let vertex_colors = parallel::map_range(self.colors_pairs.len(), |i| {
+                let (c1, c2) = &self.colors_pairs[i];
+                Color::interpolation(c1.clone(), c2.clone(), ratios[i])
+            });
+            object = object.with_vertex_colors(vertex_colors);
+        }
+        object
+    }
+
+    // Start of the morph with the straight way to its end as the morph target,
+    // the trajectory and the mask are not kept. `keyframes` are the weights
+    // of the target in time.
+    pub fn morph_mesh(&self, keyframes: Vec<(f64, f64)>) -> MorphMesh {
+        let unit = |v: Vertex| if v.len() < EPS { v } else { v / v.len() };
+        MorphMesh {
+            positions: self.vertexes_pairs.iter().map(|(v1, _)| *v1).collect(),
+            normals: self.normals_pairs.iter().map(|(n1, _)| unit(*n1)).collect(),
+            texcoords: self.uv_pairs.iter().map(|(t1, _)| *t1).collect(),
+            colors: self.colors_pairs.iter().map(|(c1, _)| c1.clone()).collect(),
+            position_deltas: self
+                .vertexes_pairs
+                .iter()
+                .map(|(v1, v2)| *v2 - *v1)
+                .collect(),
+            normal_deltas: self
+                .normals_pairs
+                .iter()
+                .map(|(n1, n2)| unit(*n2) - unit(*n1))
+                .collect(),
+            indexes: self.faces.iter().flatten().map(|&v| v as u32).collect(),
+            color: self.color_pairs.0.clone(),
+            keyframes,
+        }
     }
 }
 
 impl MergedObject {
-    fn resolve_faces(verts: &Vec<Vertex>, edges: &EdgeSet) -> Vec<Vec<usize>> {
-        let n = verts.len();
-        let mut graph = Graph::new(verts);
-        for e in edges.iter() {
-            graph.add_pair(e.from, e.to);
+    // Share of the way made by each vertex at `ratio` of the whole morph
+    fn local_ratios(&self, ratio: f64) -> Vec<f64> {
+        if self.mask_weights.is_empty() {
+            return vec![ratio; self.vertexes_pairs.len()];
         }
+        let mask = &self.ratio_mask;
+        self.mask_weights
+            .iter()
+            .map(|&weight| {
+                if self.is_mask_reversed {
+                    1. - mask.local_ratio(1. - ratio, weight)
+                } else {
+                    mask.local_ratio(ratio, weight)
+                }
+            })
+            .collect()
+    }
 
-        for i in 0..n {
-            let v = verts[i];
-            let v_len = v.len();
-            let m = graph.neighbors_count(i);
-            if m < 1 {
-                continue;
-            }
-            let first = verts[graph.neighbors(i).next().unwrap().borrow().to];
-            let mut first_dir = first - v * (v * first / v_len);
-            first_dir.normalize();
-            let mut adj_edges = graph
-                .neighbors(i)
-                .map(|e| {
-                    let p = verts[e.borrow().to];
-                    let mut dir = p - v * (v * p / v_len);
-                    dir.normalize();
-                    let norm = first_dir ^ dir;
-                    let cos = first_dir * dir;
-                    let mut angle = if (cos - 1.).abs() < EPS {
-                        0.
-                    } else if (cos + 1.).abs() < EPS {
-                        PI
-                    } else {
-                        cos.acos()
-                    };
-                    if v * norm < -EPS {
-                        angle = -angle;
-                    }
-                    (angle, e)
-                })
-                .collect::<Vec<(f64, &RcGraphEdge)>>();
-            adj_edges.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
-            for j in 0..m {
-                let k = if j == m - 1 { 0 } else { j + 1 };
-                adj_edges[j].1.borrow_mut().next = std::rc::Rc::downgrade(&adj_edges[k].1);
-            }
+    // The side without UVs borrows them from the other one, so the mapping stays fixed
+    fn resolve_uv_pairs(
+        texcoords_pairs: Vec<(Option<TexCoord>, Option<TexCoord>)>,
+    ) -> Vec<(TexCoord, TexCoord)> {
+        let has_src = texcoords_pairs.iter().any(|(t1, _)| t1.is_some());
+        let has_dst = texcoords_pairs.iter().any(|(_, t2)| t2.is_some());
+        if !has_src && !has_dst {
+            return Vec::new();
         }
 
-        let mut faces = Vec::new();
-        for i in 0..n {
-            for e in graph.neighbors(i) {
-                let mut e = e.clone();
-                let mut one_face = Vec::new();
-                while !e.borrow().visited {
-                    let p = e.borrow().to;
-                    one_face.push(p);
-                    e.borrow_mut().visited = true;
-                    let o = e.borrow().oppo.upgrade().expect("No opposite edge!");
-                    let n = o.borrow().next.upgrade().expect("No next edge");
-                    e = n;
-                }
-                if one_face.len() > 2 {
-                    faces.push(one_face);
-                }
+        texcoords_pairs
+            .into_iter()
+            .map(|(t1, t2)| match (t1, t2) {
+                (Some(t1), Some(t2)) => (t1, t2),
+                (Some(t), None) | (None, Some(t)) => (t, t),
+                (None, None) => ((0., 0.), (0., 0.)),
+            })
+            .collect()
+    }
+
+    // Each merged face lies inside one face of both objects, found by its centroid
+    fn resolve_materials(
+        src_proj: &Projection,
+        dst_proj: &Projection,
+        verts: &[Vertex],
+        faces: &[Vec<usize>],
+    ) -> Result<(MaterialPairs, Vec<usize>), MorphError> {
+        if !src_proj.has_materials() && !dst_proj.has_materials() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+
+        let mut pairs_map = BTreeMap::new();
+        let mut material_pairs = Vec::new();
+        let mut face_materials = Vec::with_capacity(faces.len());
+        let face_pairs = parallel::try_map(faces, |face| {
+            let mut centroid = Vertex::default();
+            for &v in face.iter() {
+                centroid += verts[v];
             }
+
+            Ok((
+                src_proj
+                    .project_from_sphere(centroid)
+                    .map_err(|kind| Mesh::Source.error(kind))?
+                    .face,
+                dst_proj
+                    .project_from_sphere(centroid)
+                    .map_err(|kind| Mesh::Target.error(kind))?
+                    .face,
+            ))
+        })?;
+        for (src_face, dst_face) in face_pairs.into_iter() {
+            let src_material = src_proj.face_material_index(src_face);
+            let dst_material = dst_proj.face_material_index(dst_face);
+            let index = *pairs_map
+                .entry((src_material, dst_material))
+                .or_insert_with(|| {
+                    material_pairs.push((
+                        src_proj.face_material(src_face),
+                        dst_proj.face_material(dst_face),
+                    ));
+                    material_pairs.len() - 1
+                });
+            face_materials.push(index);
         }
 
-        faces
+        Ok((material_pairs, face_materials))
     }
 }
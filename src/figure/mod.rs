@@ -1,9 +1,28 @@
+pub mod alignment;
 pub mod arc;
+pub mod arc_grid;
+pub mod blend_shape;
+pub mod bvh;
 pub mod edge;
 pub mod edge_set;
+pub mod gltf;
 pub mod graph;
+pub mod harmonic;
+pub mod kernel;
+pub mod material;
+pub mod matrix;
 pub mod merged_object;
+pub mod morph_chain;
+pub mod morph_error;
+pub mod mtl_parser;
+pub mod obj_parser;
 pub mod object;
+pub mod overlay;
+pub mod progress;
 pub mod projection;
+pub mod ratio_mask;
+pub mod sphere_warp;
+pub mod star_check;
+pub mod trajectory;
 pub mod triangle;
 pub mod vertex;
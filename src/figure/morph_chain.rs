@@ -0,0 +1,116 @@
+use std::fmt;
+
+use super::{
+    gltf::MorphMesh, merged_object::MergedObject, morph_error::MorphError, object::Object,
+    ratio_mask::RatioMask, trajectory::Trajectory,
+};
+
+// Morph flowing through several models, each one merged with the next
+pub struct MorphChain {
+    links: Vec<(MergedObject, bool)>, // merged pair, whether the later model is its source
+}
+
+// Failed link between the models `link` and `link + 1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainError {
+    pub link: usize,
+    pub nlinks: usize,
+    pub error: MorphError,
+}
+
+impl MorphChain {
+    // `merge` gets the link, its source and target models and their matching
+    // vertexes `(src, dst)` taken from the landmarks. The source is the model
+    // with more vertexes, errors are reported for the models in chain order.
+    pub fn new<F>(models: Vec<Object>, mut merge: F) -> Result<Self, ChainError>
+    where
+        F: FnMut(usize, Object, Object, Vec<(usize, usize)>) -> Result<MergedObject, MorphError>,
+    {
+        let nlinks = models.len().saturating_sub(1);
+        let mut links = Vec::with_capacity(nlinks);
+        for (link, pair) in models.windows(2).enumerate() {
+            let (start, result) = (&pair[0], &pair[1]);
+            let mut pairs = start
+                .landmarks()
+                .iter()
+                .copied()
+                .zip(result.landmarks().iter().copied())
+                .collect::<Vec<_>>();
+            let is_swap = start.nvertexes() < result.nvertexes();
+            let (src, dst) = if is_swap {
+                pairs.iter_mut().for_each(|(a, b)| std::mem::swap(a, b));
+                (result.clone(), start.clone())
+            } else {
+                (start.clone(), result.clone())
+            };
+            let merged = merge(link, src, dst, pairs).map_err(|e| ChainError {
+                link,
+                nlinks,
+                error: if is_swap { e.swapped() } else { e },
+            })?;
+            links.push((merged, is_swap));
+        }
+
+        Ok(Self { links })
+    }
+
+    pub fn nmodels(&self) -> usize {
+        self.links.len() + 1
+    }
+
+    pub fn set_trajectory(&mut self, trajectory: Trajectory) {
+        for (merged, _) in self.links.iter_mut() {
+            merged.set_trajectory(trajectory);
+        }
+    }
+
+    // The mask is laid on the model each link starts from
+    pub fn set_mask(&mut self, mask: RatioMask) {
+        for (merged, is_swap) in self.links.iter_mut() {
+            merged.set_mask(mask, *is_swap);
+        }
+    }
+
+    // Only a chain of two models is one mesh, the weight of its target is
+    // the ratio of the morph at the keyframes (seconds, ratio)
+    pub fn morph_mesh(&self, keyframes: &[(f64, f64)]) -> Option<MorphMesh> {
+        let [(merged, is_swap)] = self.links.as_slice() else {
+            return None;
+        };
+        let keyframes = keyframes
+            .iter()
+            .map(|&(time, ratio)| (time, if *is_swap { 1. - ratio } else { ratio }))
+            .collect();
+
+        Some(merged.morph_mesh(keyframes))
+    }
+
+    // Each link takes an equal share of `progress`, from 0 at the first model
+    // to 1 at the last one
+    pub fn interpolation(&self, progress: f64) -> Object {
+        let n = self.links.len();
+        let x = progress.clamp(0., 1.) * n as f64;
+        let link = (x.floor() as usize).min(n - 1);
+        let ratio = x - link as f64;
+        let (merged, is_swap) = &self.links[link];
+
+        merged.interpolation(if *is_swap { 1. - ratio } else { ratio })
+    }
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.nlinks > 1 {
+            write!(
+                f,
+                "переход от модели {} к модели {}: ",
+                self.link + 1,
+                self.link + 2
+            )?;
+        }
+
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for ChainError {}
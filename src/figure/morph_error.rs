@@ -0,0 +1,143 @@
+use std::fmt;
+
+// Vertex and face indices start from 0, messages show them from 1 as in OBJ
+#[derive(Debug, Clone, PartialEq)]
+pub enum MorphError {
+    Mesh { mesh: Mesh, kind: MeshErrorKind },
+    // the overlay of both models is not a closed subdivision of the sphere,
+    // `vertex` is the closest vertex of `mesh` to the failure
+    Overlay { mesh: Mesh, vertex: usize },
+    Cancelled,
+    // the worker thread panicked, with the panic message
+    Panicked(String),
+}
+
+// Models are merged as a source and a target, the source being the one with
+// more vertexes, so callers swap them back if needed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mesh {
+    Source,
+    Target,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeshErrorKind {
+    // a ray from the center misses every face near the vertex
+    NotStarShaped(usize),
+    // face with zero area
+    DegenerateFace(usize),
+    // edge shared by more than two faces
+    NonManifold(usize, usize),
+    // vertex with an infinite or NaN coordinate
+    Numeric(usize),
+    // edge of a single face, the model has a hole
+    Boundary(usize, usize),
+    // Euler characteristic of a model which is not a closed genus 0 surface
+    NotSphere(i64),
+    // vertex not connected to the first face
+    Disconnected(usize),
+    // face turned over by the warp matching the picked points
+    Warp(usize),
+    // face still turned over on the sphere after untangling the harmonic map
+    Fold(usize),
+}
+
+impl MorphError {
+    pub fn swapped(self) -> Self {
+        match self {
+            MorphError::Mesh { mesh, kind } => MorphError::Mesh {
+                mesh: mesh.other(),
+                kind,
+            },
+            MorphError::Overlay { mesh, vertex } => MorphError::Overlay {
+                mesh: mesh.other(),
+                vertex,
+            },
+            MorphError::Cancelled | MorphError::Panicked(_) => self,
+        }
+    }
+}
+
+impl Mesh {
+    pub fn error(self, kind: MeshErrorKind) -> MorphError {
+        MorphError::Mesh { mesh: self, kind }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            Mesh::Source => Mesh::Target,
+            Mesh::Target => Mesh::Source,
+        }
+    }
+}
+
+impl fmt::Display for MorphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MorphError::Mesh { mesh, kind } => write!(f, "{mesh}: {kind}"),
+            MorphError::Overlay { mesh, vertex } => write!(
+                f,
+                "не удалось совместить модели около вершины {} ({mesh})",
+                vertex + 1
+            ),
+            MorphError::Cancelled => write!(f, "морфинг отменён"),
+            MorphError::Panicked(message) => write!(f, "внутренняя ошибка: {message}"),
+        }
+    }
+}
+
+impl fmt::Display for Mesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mesh::Source => write!(f, "стартовая модель"),
+            Mesh::Target => write!(f, "итоговая модель"),
+        }
+    }
+}
+
+impl fmt::Display for MeshErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshErrorKind::NotStarShaped(v) => write!(
+                f,
+                "луч из центра не пересекает поверхность около вершины {}",
+                v + 1
+            ),
+            MeshErrorKind::DegenerateFace(face) => {
+                write!(f, "треугольник {} нулевой площади", face + 1)
+            }
+            MeshErrorKind::NonManifold(a, b) => write!(
+                f,
+                "ребро {}-{} принадлежит более чем двум граням",
+                a + 1,
+                b + 1
+            ),
+            MeshErrorKind::Numeric(v) => write!(f, "некорректные координаты вершины {}", v + 1),
+            MeshErrorKind::Boundary(a, b) => write!(
+                f,
+                "ребро {}-{} принадлежит одной грани, модель не замкнута",
+                a + 1,
+                b + 1
+            ),
+            MeshErrorKind::NotSphere(euler) => write!(
+                f,
+                "модель не гомеоморфна сфере (эйлерова характеристика {euler})"
+            ),
+            MeshErrorKind::Disconnected(v) => {
+                write!(f, "вершина {} не связана с остальной моделью", v + 1)
+            }
+            MeshErrorKind::Warp(face) => write!(
+                f,
+                "точки соответствия перекручивают отображение около треугольника {}",
+                face + 1
+            ),
+            MeshErrorKind::Fold(face) => write!(
+                f,
+                "треугольник {} перевёрнут при отображении на сферу",
+                face + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MorphError {}
@@ -0,0 +1,218 @@
+use super::material::Material;
+use super::obj_parser::{read_records, ObjError, ObjErrorKind};
+use crate::color::Color;
+use crate::texture::Texture;
+
+use std::io::{BufRead, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+pub struct MtlParser {
+    materials: Vec<Material>,
+    dir: PathBuf, // textures are resolved relative to the library
+    line: usize,
+}
+
+impl MtlParser {
+    pub fn parse<R: BufRead>(reader: R, dir: &Path) -> Result<Vec<Material>, ObjError> {
+        let mut parser = Self {
+            materials: Vec::new(),
+            dir: dir.to_path_buf(),
+            line: 0,
+        };
+        read_records(reader, |line, record| {
+            parser.line = line;
+            parser.parse_record(record)
+        })?;
+
+        Ok(parser.materials)
+    }
+}
+
+impl MtlParser {
+    fn error(&self, kind: ObjErrorKind) -> ObjError {
+        ObjError::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn parse_number(&self, val: Option<&str>, what: &'static str) -> Result<f64, ObjError> {
+        let val = val.ok_or_else(|| self.error(ObjErrorKind::MissingValue(what)))?;
+
+        val.parse()
+            .map_err(|_| self.error(ObjErrorKind::InvalidNumber(val.to_string())))
+    }
+
+    // `g` and `b` may be omitted, then they are equal to `r`
+    fn parse_color(&self, vals: &mut SplitWhitespace, alpha: u8) -> Result<Color, ObjError> {
+        let r = self.parse_number(vals.next(), "r g b")?;
+        let g = vals
+            .next()
+            .map_or(Ok(r), |g| self.parse_number(Some(g), "g"))?;
+        let b = vals
+            .next()
+            .map_or(Ok(r), |b| self.parse_number(Some(b), "b"))?;
+
+        Ok(Color::from_unit(r, g, b, alpha as f64 / 255.))
+    }
+
+    fn parse_texture(&self, vals: SplitWhitespace) -> Result<Option<Texture>, ObjError> {
+        // options like `-s 1 1 1` go before the file name
+        let filename = vals
+            .last()
+            .ok_or_else(|| self.error(ObjErrorKind::MissingValue("map_Kd")))?;
+
+        match Texture::load(self.dir.join(filename)) {
+            Ok(texture) => Ok(Some(texture)),
+            Err(image::ImageError::IoError(e)) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(self.error(ObjErrorKind::InvalidTexture(e.to_string()))),
+        }
+    }
+
+    fn parse_record(&mut self, record: &str) -> Result<(), ObjError> {
+        let mut vals = record.split_whitespace();
+        let Some(keyword) = vals.next() else {
+            return Ok(());
+        };
+        if keyword == "newmtl" {
+            self.materials
+                .push(Material::new(vals.next().unwrap_or_default()));
+            return Ok(());
+        }
+        let Some(mut material) = self.materials.pop() else {
+            return Ok(());
+        };
+
+        let result = self.parse_property(&mut material, keyword, vals);
+        self.materials.push(material);
+        result
+    }
+
+    fn parse_property(
+        &self,
+        material: &mut Material,
+        keyword: &str,
+        mut vals: SplitWhitespace,
+    ) -> Result<(), ObjError> {
+        match keyword {
+            "Ka" => material.ambient = self.parse_color(&mut vals, 255)?,
+            "Kd" => material.diffuse = self.parse_color(&mut vals, material.diffuse.a())?,
+            "Ks" => material.specular = self.parse_color(&mut vals, 255)?,
+            "Ns" => material.shininess = self.parse_number(vals.next(), "Ns")?,
+            "d" | "Tr" => {
+                let mut val = vals.next();
+                if val == Some("-halo") {
+                    val = vals.next();
+                }
+                let mut alpha = self.parse_number(val, "d")?;
+                if keyword == "Tr" {
+                    alpha = 1. - alpha;
+                }
+                let d = &material.diffuse;
+                material.diffuse = Color::from_unit(
+                    d.r() as f64 / 255.,
+                    d.g() as f64 / 255.,
+                    d.b() as f64 / 255.,
+                    alpha,
+                );
+            }
+            "map_Kd" => material.texture = self.parse_texture(vals)?.map(Arc::new),
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    fn parse(text: &str) -> Vec<Material> {
+        MtlParser::parse(text.as_bytes(), Path::new("")).unwrap()
+    }
+
+    // Directory of its own for each test, removed by the test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("morphing_mtl_{name}_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn colors() {
+        let materials =
+            parse("newmtl a\nKa 0.2\nKd 1 0.5 0 # orange\nKs 0 0 1\nNs 10\n\nnewmtl b\nKd 0 1 0\n");
+        assert_eq!(materials.len(), 2);
+        let a = &materials[0];
+        assert_eq!(a.name, "a");
+        assert_eq!(a.ambient.to_array(), [51, 51, 51, 255]);
+        assert_eq!(a.diffuse.to_array(), [255, 128, 0, 255]);
+        assert_eq!(a.specular.to_array(), [0, 0, 255, 255]);
+        assert_eq!(a.shininess, 10.);
+        assert_eq!(materials[1].diffuse.to_array(), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn transparency() {
+        let alpha = |text: &str| parse(text)[0].diffuse.a();
+        assert_eq!(alpha("newmtl a\nd 0.25\n"), 64);
+        // `Tr` is the inverse of `d`
+        assert_eq!(alpha("newmtl a\nTr 0.25\n"), 191);
+        assert_eq!(alpha("newmtl a\nd -halo 0.5\n"), 128);
+        // `Kd` after `d` keeps the alpha
+        assert_eq!(alpha("newmtl a\nd 0.5\nKd 1 0 0\n"), 128);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| match MtlParser::parse(text.as_bytes(), Path::new("")) {
+            Err(ObjError::Parse { line, kind }) => (line, kind),
+            _ => panic!("no error"),
+        };
+        assert_eq!(
+            error("newmtl a\n\nKd x\n"),
+            (3, ObjErrorKind::InvalidNumber("x".to_string()))
+        );
+        assert_eq!(
+            error("newmtl a\nNs\n"),
+            (2, ObjErrorKind::MissingValue("Ns"))
+        );
+        assert_eq!(
+            error("newmtl a\nmap_Kd\n"),
+            (2, ObjErrorKind::MissingValue("map_Kd"))
+        );
+    }
+
+    #[test]
+    fn textures() {
+        let dir = temp_dir("textures");
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 0, 255, 255]))
+            .save(dir.join("blue.png"))
+            .unwrap();
+        fs::write(dir.join("broken.png"), b"not an image").unwrap();
+        let parse = |text: &str| MtlParser::parse(text.as_bytes(), &dir);
+
+        // options go before the file name
+        let loaded = parse("newmtl a\nmap_Kd -s 1 1 1 blue.png\n");
+        // a missing texture is skipped, the model is still drawn
+        let missing = parse("newmtl a\nmap_Kd missing.png\n");
+        let broken = parse("newmtl a\nKd 1 0 0\nmap_Kd broken.png\n");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let texture = loaded.unwrap()[0].texture.clone().unwrap();
+        assert_eq!((texture.width(), texture.height()), (2, 2));
+        assert!(missing.unwrap()[0].texture.is_none());
+        match broken {
+            Err(ObjError::Parse {
+                line: 3,
+                kind: ObjErrorKind::InvalidTexture(_),
+            }) => {}
+            _ => panic!("the broken texture is not reported"),
+        }
+    }
+}
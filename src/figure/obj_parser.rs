@@ -0,0 +1,517 @@
+use super::vertex::{TexCoord, Vertex};
+use crate::color::Color;
+
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::{FromStr, SplitWhitespace};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        kind: ObjErrorKind,
+    },
+    Mtl {
+        filename: String,
+        line: usize,
+        kind: ObjErrorKind,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjErrorKind {
+    // record has fewer values than required
+    MissingValue(&'static str),
+    // value is not a number
+    InvalidNumber(String),
+    // malformed `v/vt/vn` reference
+    InvalidIndex(String),
+    // indices in OBJ start from 1
+    ZeroIndex,
+    // reference to a record that does not exist
+    IndexOutOfRange(&'static str, isize),
+    // face with less than 3 vertices
+    DegenerateFace(usize),
+    // `map_Kd` image exists but can not be decoded
+    InvalidTexture(String),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "ошибка чтения: {e}"),
+            ObjError::Parse { line, kind } => write!(f, "строка {line}: {kind}"),
+            ObjError::Mtl {
+                filename,
+                line,
+                kind,
+            } => write!(f, "{filename}, строка {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ObjErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjErrorKind::MissingValue(what) => write!(f, "не хватает значения {what}"),
+            ObjErrorKind::InvalidNumber(s) => write!(f, "некорректное число «{s}»"),
+            ObjErrorKind::InvalidIndex(s) => write!(f, "некорректная ссылка «{s}»"),
+            ObjErrorKind::ZeroIndex => write!(f, "индекс 0 недопустим"),
+            ObjErrorKind::IndexOutOfRange(what, i) => write!(f, "индекс {what} {i} вне диапазона"),
+            ObjErrorKind::DegenerateFace(n) => write!(f, "грань из {n} вершин"),
+            ObjErrorKind::InvalidTexture(e) => write!(f, "некорректная текстура: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io(e) => Some(e),
+            ObjError::Parse { .. } | ObjError::Mtl { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for ObjError {
+    fn from(e: io::Error) -> Self {
+        ObjError::Io(e)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FaceVertex {
+    v: usize,
+    vt: Option<usize>,
+    vn: Option<usize>,
+    line: usize,
+}
+
+#[derive(Default)]
+pub struct ObjParser {
+    vertexes: Vec<Vertex>,
+    vertex_colors: Vec<Color>,
+    normals: Vec<Vertex>,
+    texcoords: Vec<TexCoord>,
+    faces: Vec<[FaceVertex; 3]>,
+    material_libs: Vec<String>,
+    material_names: Vec<String>,
+    face_materials: Vec<Option<usize>>,
+    material: Option<usize>,
+    line: usize,
+}
+
+pub struct ObjData {
+    pub vertexes: Vec<Vertex>,
+    pub vertex_colors: Option<Vec<Color>>, // `None` if no vertex has a colour
+    pub faces: Vec<Vec<(usize, usize)>>,   // (v, vn), vn equals v if there are no normals
+    pub normals: Option<Vec<Vertex>>,      // `None` if some face vertex has no `vn`
+    pub texcoords: Vec<TexCoord>,
+    pub face_texcoords: Option<Vec<Vec<usize>>>, // `None` if some face vertex has no `vt`
+    pub material_libs: Vec<String>,
+    pub material_names: Vec<String>,
+    pub face_materials: Vec<Option<usize>>, // index in `material_names` for each face
+}
+
+// Calls `f` for each record with its first line number. Comments are stripped
+// and lines ending with `\` are joined with the next one.
+pub fn read_records<R, F>(reader: R, mut f: F) -> Result<(), ObjError>
+where
+    R: BufRead,
+    F: FnMut(usize, &str) -> Result<(), ObjError>,
+{
+    let mut record = String::new();
+    let mut record_line = 0;
+    for (ind, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => line.as_str(),
+        };
+        if record.is_empty() {
+            record_line = ind + 1;
+        }
+        if let Some(part) = line.trim_end().strip_suffix('\\') {
+            record.push_str(part);
+            record.push(' ');
+            continue;
+        }
+        record.push_str(line);
+        f(record_line, &record)?;
+        record.clear();
+    }
+    if !record.is_empty() {
+        f(record_line, &record)?;
+    }
+
+    Ok(())
+}
+
+impl ObjParser {
+    pub fn parse<R: BufRead>(reader: R) -> Result<ObjData, ObjError> {
+        let mut parser = Self::default();
+        read_records(reader, |line, record| {
+            parser.line = line;
+            parser.parse_record(record)
+        })?;
+
+        parser.finish()
+    }
+}
+
+impl ObjParser {
+    fn error(&self, kind: ObjErrorKind) -> ObjError {
+        ObjError::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn parse_record(&mut self, record: &str) -> Result<(), ObjError> {
+        let mut vals = record.split_whitespace();
+        let Some(keyword) = vals.next() else {
+            return Ok(());
+        };
+        match keyword {
+            "v" => {
+                let vertex = self.parse_vertex(&mut vals, "x y z")?;
+                self.vertexes.push(vertex);
+                // `v x y z r g b` colour extension, `v x y z w` is left as is
+                let rest = vals.collect::<Vec<_>>();
+                if rest.len() >= 3 {
+                    let r = self.parse_number(Some(rest[0]), "r")?;
+                    let g = self.parse_number(Some(rest[1]), "g")?;
+                    let b = self.parse_number(Some(rest[2]), "b")?;
+                    self.vertex_colors
+                        .resize(self.vertexes.len() - 1, Color::new([255; 4]));
+                    self.vertex_colors.push(Color::from_unit(r, g, b, 1.));
+                }
+            }
+            "vn" => {
+                let mut normal = self.parse_vertex(&mut vals, "нормали")?;
+                normal.normalize();
+                self.normals.push(normal);
+            }
+            "vt" => {
+                let u = self.parse_number(vals.next(), "u")?;
+                let v = match vals.next() {
+                    Some(v) => self.parse_number(Some(v), "v")?,
+                    None => 0.,
+                };
+                self.texcoords.push((u, v));
+            }
+            "f" => self.parse_face(vals)?,
+            "mtllib" => self.material_libs.extend(vals.map(String::from)),
+            "usemtl" => {
+                let name = vals.next().unwrap_or_default();
+                let index = match self.material_names.iter().position(|n| n == name) {
+                    Some(index) => index,
+                    None => {
+                        self.material_names.push(name.to_string());
+                        self.material_names.len() - 1
+                    }
+                };
+                self.material = Some(index);
+            }
+            // `o`, `g`, `s` and other records do not affect the geometry
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn parse_number<T: FromStr>(
+        &self,
+        val: Option<&str>,
+        what: &'static str,
+    ) -> Result<T, ObjError> {
+        let val = val.ok_or_else(|| self.error(ObjErrorKind::MissingValue(what)))?;
+
+        val.parse()
+            .map_err(|_| self.error(ObjErrorKind::InvalidNumber(val.to_string())))
+    }
+
+    fn parse_vertex(
+        &self,
+        vals: &mut SplitWhitespace,
+        what: &'static str,
+    ) -> Result<Vertex, ObjError> {
+        Ok(Vertex::new(
+            self.parse_number(vals.next(), what)?,
+            self.parse_number(vals.next(), what)?,
+            self.parse_number(vals.next(), what)?,
+        ))
+    }
+
+    fn resolve_index(
+        &self,
+        val: &str,
+        count: usize,
+        what: &'static str,
+    ) -> Result<usize, ObjError> {
+        let index = val
+            .parse::<isize>()
+            .map_err(|_| self.error(ObjErrorKind::InvalidIndex(val.to_string())))?;
+        match index {
+            0 => Err(self.error(ObjErrorKind::ZeroIndex)),
+            // positive indices may refer to records defined later, checked in `finish`
+            i if i > 0 => Ok(i as usize - 1),
+            i if i.unsigned_abs() <= count => Ok(count - i.unsigned_abs()),
+            i => Err(self.error(ObjErrorKind::IndexOutOfRange(what, i))),
+        }
+    }
+
+    fn parse_face_vertex(&self, val: &str) -> Result<FaceVertex, ObjError> {
+        let invalid = || self.error(ObjErrorKind::InvalidIndex(val.to_string()));
+        let mut g = val.split('/');
+        let v = g.next().ok_or_else(invalid)?;
+        let vt = g.next().unwrap_or("");
+        let vn = g.next().unwrap_or("");
+        if g.next().is_some() {
+            return Err(invalid());
+        }
+
+        let v = self.resolve_index(v, self.vertexes.len(), "v")?;
+        let vt = if vt.is_empty() {
+            None
+        } else {
+            Some(self.resolve_index(vt, self.texcoords.len(), "vt")?)
+        };
+        let vn = if vn.is_empty() {
+            None
+        } else {
+            Some(self.resolve_index(vn, self.normals.len(), "vn")?)
+        };
+
+        Ok(FaceVertex {
+            v,
+            vt,
+            vn,
+            line: self.line,
+        })
+    }
+
+    fn parse_face(&mut self, vals: SplitWhitespace) -> Result<(), ObjError> {
+        let face = vals
+            .map(|val| self.parse_face_vertex(val))
+            .collect::<Result<Vec<_>, _>>()?;
+        if face.len() < 3 {
+            return Err(self.error(ObjErrorKind::DegenerateFace(face.len())));
+        }
+
+        for i in 1..face.len() - 1 {
+            self.faces.push([face[0], face[i], face[i + 1]]);
+            self.face_materials.push(self.material);
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<ObjData, ObjError> {
+        // vertices without a colour stay white
+        let vertex_colors = !self.vertex_colors.is_empty();
+        self.vertex_colors
+            .resize(self.vertexes.len(), Color::new([255; 4]));
+        let has_normals = self
+            .faces
+            .iter()
+            .all(|face| face.iter().all(|fv| fv.vn.is_some()));
+        let has_texcoords = self
+            .faces
+            .iter()
+            .all(|face| face.iter().all(|fv| fv.vt.is_some()));
+        let mut faces = Vec::with_capacity(self.faces.len());
+        let mut face_texcoords = Vec::with_capacity(self.faces.len());
+        for face in self.faces.iter() {
+            let mut indexes = Vec::with_capacity(3);
+            for fv in face.iter() {
+                let error = |kind| ObjError::Parse {
+                    line: fv.line,
+                    kind,
+                };
+                if fv.v >= self.vertexes.len() {
+                    return Err(error(ObjErrorKind::IndexOutOfRange("v", fv.v as isize + 1)));
+                }
+                if let Some(vt) = fv.vt.filter(|&vt| vt >= self.texcoords.len()) {
+                    return Err(error(ObjErrorKind::IndexOutOfRange("vt", vt as isize + 1)));
+                }
+                if let Some(vn) = fv.vn.filter(|&vn| vn >= self.normals.len()) {
+                    return Err(error(ObjErrorKind::IndexOutOfRange("vn", vn as isize + 1)));
+                }
+                let vn = if has_normals {
+                    fv.vn.unwrap_or(fv.v)
+                } else {
+                    fv.v
+                };
+                indexes.push((fv.v, vn));
+            }
+            faces.push(indexes);
+            if has_texcoords {
+                face_texcoords.push(face.iter().map(|fv| fv.vt.unwrap_or(0)).collect());
+            }
+        }
+
+        Ok(ObjData {
+            vertex_colors: vertex_colors.then_some(self.vertex_colors),
+            vertexes: self.vertexes,
+            faces,
+            normals: has_normals.then_some(self.normals),
+            texcoords: self.texcoords,
+            face_texcoords: has_texcoords.then_some(face_texcoords),
+            material_libs: self.material_libs,
+            material_names: self.material_names,
+            face_materials: self.face_materials,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    fn parse(text: &str) -> ObjData {
+        ObjParser::parse(text.as_bytes()).unwrap()
+    }
+
+    fn parse_error(text: &str) -> (usize, ObjErrorKind) {
+        match ObjParser::parse(text.as_bytes()) {
+            Err(ObjError::Parse { line, kind }) => (line, kind),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("no error"),
+        }
+    }
+
+    fn vertexes(data: &ObjData) -> Vec<Vec<usize>> {
+        data.faces
+            .iter()
+            .map(|face| face.iter().map(|&(v, _)| v).collect())
+            .collect()
+    }
+
+    #[test]
+    fn face_forms() {
+        let data = parse(&format!("{TRIANGLE}f 1 2 3\n"));
+        assert_eq!(data.faces, vec![vec![(0, 0), (1, 1), (2, 2)]]);
+        assert!(data.normals.is_none());
+        assert!(data.face_texcoords.is_none());
+
+        let data = parse(&format!(
+            "{TRIANGLE}vt 0 0\nvt 1 0\nvt 0 1\nf 1/3 2/2 3/1\n"
+        ));
+        assert_eq!(vertexes(&data), vec![vec![0, 1, 2]]);
+        assert_eq!(data.face_texcoords, Some(vec![vec![2, 1, 0]]));
+        assert!(data.normals.is_none());
+
+        let data = parse(&format!("{TRIANGLE}vn 0 0 2\nf 1//1 2//1 3//1\n"));
+        assert_eq!(data.faces, vec![vec![(0, 0), (1, 0), (2, 0)]]);
+        assert!(data.face_texcoords.is_none());
+        let normals = data.normals.unwrap();
+        assert_eq!((normals[0].x, normals[0].y, normals[0].z), (0., 0., 1.));
+
+        let data = parse(&format!(
+            "{TRIANGLE}vt 0 0\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1\n"
+        ));
+        assert_eq!(data.faces, vec![vec![(0, 0), (1, 0), (2, 0)]]);
+        assert_eq!(data.face_texcoords, Some(vec![vec![0, 0, 0]]));
+        assert!(data.normals.is_some());
+    }
+
+    #[test]
+    fn negative_indices() {
+        let data = parse(&format!("{TRIANGLE}f -3 -2 -1\nv 1 1 0\nf -4 -1 -3\n"));
+        assert_eq!(vertexes(&data), vec![vec![0, 1, 2], vec![0, 3, 1]]);
+    }
+
+    #[test]
+    fn polygon_fan() {
+        let data = parse(&format!(
+            "{TRIANGLE}v 1 1 0\nv 2 1 0\nusemtl red\nf 1 2 4 3 5\n"
+        ));
+        assert_eq!(
+            vertexes(&data),
+            vec![vec![0, 1, 3], vec![0, 3, 2], vec![0, 2, 4]]
+        );
+        assert_eq!(data.material_names, vec!["red".to_string()]);
+        assert_eq!(data.face_materials, vec![Some(0); 3]);
+    }
+
+    #[test]
+    fn comments_and_continuations() {
+        let data = parse("# header\nv 0 0 0 # origin\nv 1 \\\n 0 0\nv 0 1 0\nf 1 2 \\\n3 # face\n");
+        assert_eq!(data.vertexes.len(), 3);
+        assert_eq!(data.vertexes[1].x, 1.);
+        assert_eq!(vertexes(&data), vec![vec![0, 1, 2]]);
+
+        // a record joined from several lines reports its first line
+        let (line, kind) = parse_error("v 0 0 0\nv 1 \\\n0 x\n");
+        assert_eq!(line, 2);
+        assert_eq!(kind, ObjErrorKind::InvalidNumber("x".to_string()));
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let data = parse("v 0 0 0 1 0 0\nv 1 0 0\nv 0 1 0 1\nf 1 2 3\n");
+        let colors = data.vertex_colors.unwrap();
+        assert_eq!(colors[0].to_array(), [255, 0, 0, 255]);
+        assert_eq!(colors[1].to_array(), [255; 4]);
+        assert_eq!(colors[2].to_array(), [255; 4]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse_error("v 0 0 0\nv 1 0\n"),
+            (2, ObjErrorKind::MissingValue("x y z"))
+        );
+        assert_eq!(
+            parse_error("v 0 0 0\n\nvt a\n"),
+            (3, ObjErrorKind::InvalidNumber("a".to_string()))
+        );
+        assert_eq!(
+            parse_error(&format!("{TRIANGLE}f 1 2 3/a\n")),
+            (4, ObjErrorKind::InvalidIndex("a".to_string()))
+        );
+        assert_eq!(
+            parse_error(&format!("{TRIANGLE}f 1 2 3/1/1/1\n")),
+            (4, ObjErrorKind::InvalidIndex("3/1/1/1".to_string()))
+        );
+        assert_eq!(
+            parse_error(&format!("{TRIANGLE}f 0 1 2\n")),
+            (4, ObjErrorKind::ZeroIndex)
+        );
+        assert_eq!(
+            parse_error(&format!("{TRIANGLE}f 1 2 -4\n")),
+            (4, ObjErrorKind::IndexOutOfRange("v", -4))
+        );
+        assert_eq!(
+            parse_error(&format!("{TRIANGLE}\nf 1 2\n")),
+            (5, ObjErrorKind::DegenerateFace(2))
+        );
+    }
+
+    #[test]
+    fn forward_references() {
+        // positive indices may point to records further in the file
+        let data = parse("f 1 2 3\nv 0 0 0\nv 1 0 0\nv 0 1 0\n");
+        assert_eq!(vertexes(&data), vec![vec![0, 1, 2]]);
+
+        // and are checked when the whole file is read, at the line of the face
+        assert_eq!(
+            parse_error(&format!("f 1 2 4\n{TRIANGLE}")),
+            (1, ObjErrorKind::IndexOutOfRange("v", 4))
+        );
+        assert_eq!(
+            parse_error(&format!("{TRIANGLE}vt 0 0\nf 1/1 2/2 3/1\n")),
+            (5, ObjErrorKind::IndexOutOfRange("vt", 2))
+        );
+        assert_eq!(
+            parse_error(&format!(
+                "{TRIANGLE}f 1//1 2//1 3//1\nvn 0 0 1\nf 1//2 2//1 3//1\n"
+            )),
+            (6, ObjErrorKind::IndexOutOfRange("vn", 2))
+        );
+    }
+}
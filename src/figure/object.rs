@@ -1,9 +1,16 @@
 use crate::color::Color;
+use crate::texture::{Sampler, SharedTexture};
+use crate::EPS;
 
-use super::vertex::Vertex;
+use super::material::Material;
+use super::mtl_parser::MtlParser;
+use super::obj_parser::{ObjError, ObjParser};
+use super::triangle::Triangle;
+use super::vertex::{TexCoord, Vertex};
 use std::{
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
     slice::Iter,
 };
 
@@ -12,8 +19,17 @@ pub struct Object {
     vertexes: Vec<Vertex>,
     faces: Vec<Vec<(usize, usize)>>, // (v, vn)
     normals: Vec<Vertex>,            // normalized
+    texcoords: Vec<TexCoord>,
+    face_texcoords: Vec<Vec<usize>>, // vt for each face vertex, empty if there are no UVs
     center: Vertex,
     color: Color,
+    texture: Option<SharedTexture>,
+    materials: Vec<Material>,
+    face_materials: Vec<Option<usize>>, // material for each face, empty if there are no materials
+    vertex_colors: Vec<Color>,          // empty if there are no vertex colours
+    projection_center: Option<Vertex>, // point the model is projected to the sphere from, `center` if none
+    landmarks: Vec<usize>,             // vertexes picked to match the points of the other model
+    mask: Vec<f64>, // painted weights of the vertexes in a local morph, empty if none
 }
 
 impl Object {
@@ -28,61 +44,312 @@ impl Object {
             vertexes,
             faces,
             normals,
+            texcoords: Vec::new(),
+            face_texcoords: Vec::new(),
             center,
             color,
+            texture: None,
+            materials: Vec::new(),
+            face_materials: Vec::new(),
+            vertex_colors: Vec::new(),
+            projection_center: None,
+            landmarks: Vec::new(),
+            mask: Vec::new(),
         }
     }
 
-    pub fn load(filename: &str, color: Color) -> io::Result<Self> {
+    pub fn with_texcoords(
+        mut self,
+        texcoords: Vec<TexCoord>,
+        face_texcoords: Vec<Vec<usize>>,
+    ) -> Self {
+        self.texcoords = texcoords;
+        self.face_texcoords = face_texcoords;
+        self
+    }
+
+    pub fn with_materials(
+        mut self,
+        materials: Vec<Material>,
+        face_materials: Vec<Option<usize>>,
+    ) -> Self {
+        self.materials = materials;
+        self.face_materials = face_materials;
+        self
+    }
+
+    pub fn with_vertex_colors(mut self, vertex_colors: Vec<Color>) -> Self {
+        self.vertex_colors = vertex_colors;
+        self
+    }
+
+    pub fn load(filename: &str, color: Color) -> Result<Self, ObjError> {
         let file = File::open(filename)?;
-        let reader = BufReader::new(file);
-
-        let mut vertexes = Vec::new();
-        let mut normals = Vec::new();
-        let mut faces = Vec::new();
-        for line in reader.lines() {
-            let line = line?;
-            let vals = line.split_whitespace().collect::<Vec<_>>();
-            if vals.len() == 0 {
-                continue;
-            }
-            match vals[0] {
-                "v" => vertexes.push(Vertex::new(
-                    vals[1].parse().unwrap(),
-                    vals[2].parse().unwrap(),
-                    vals[3].parse().unwrap(),
-                )),
-                "vn" => {
-                    let mut normal = Vertex::new(
-                        vals[1].parse().unwrap(),
-                        vals[2].parse().unwrap(),
-                        vals[3].parse().unwrap(),
-                    );
+        let data = ObjParser::parse(BufReader::new(file))?;
+        let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+        let materials = Self::load_materials(dir, &data.material_libs)?;
+
+        let is_computed_normals = data.normals.is_none();
+        let mut object = Self::new(
+            data.vertexes,
+            data.faces,
+            data.normals.unwrap_or_default(),
+            color,
+        );
+        if is_computed_normals {
+            object.compute_normals(None);
+        }
+        if let Some(face_texcoords) = data.face_texcoords {
+            object = object.with_texcoords(data.texcoords, face_texcoords);
+        }
+        // `usemtl` may refer to a material missing in the libraries
+        let material_indexes = data
+            .material_names
+            .iter()
+            .map(|name| materials.iter().position(|m| m.name == *name))
+            .collect::<Vec<_>>();
+        let face_materials = data
+            .face_materials
+            .iter()
+            .map(|m| m.and_then(|m| material_indexes[m]))
+            .collect::<Vec<_>>();
+        if face_materials.iter().any(Option::is_some) {
+            object = object.with_materials(materials, face_materials);
+        }
+        if let Some(vertex_colors) = data.vertex_colors {
+            object = object.with_vertex_colors(vertex_colors);
+        }
+
+        Ok(object)
+    }
+
+    // Angle-weighted vertex normals. Faces meeting at an angle greater than
+    // `crease_angle` do not smooth each other, so hard edges stay flat-shaded.
+    pub fn compute_normals(&mut self, crease_angle: Option<f64>) {
+        let face_normals = (0..self.nfaces())
+            .map(|ind| {
+                let coords = self.face_coords(ind);
+                let mut normal = Triangle::new(coords[0], coords[1], coords[2]).normal();
+                if normal.len() > EPS {
                     normal.normalize();
-                    normals.push(normal);
                 }
-                "f" => {
-                    let mut face = vec![];
-                    for i in 1..=3 {
-                        let mut g = vals[i].split("/");
-                        let v = g.next().unwrap().parse::<usize>().unwrap() - 1;
-                        g.next(); // vt
-                        let vn = g.next().unwrap().parse::<usize>().unwrap() - 1;
-                        face.push((v, vn));
+                normal
+            })
+            .collect::<Vec<_>>();
+
+        let mut vertex_faces = vec![Vec::new(); self.nvertexes()];
+        for (face_ind, face) in self.faces.iter().enumerate() {
+            let m = face.len();
+            for k in 0..m {
+                let v = self.vertexes[face[k].0];
+                let mut a = self.vertexes[face[(k + 1) % m].0] - v;
+                let mut b = self.vertexes[face[(k + m - 1) % m].0] - v;
+                if a.len() < EPS || b.len() < EPS {
+                    continue;
+                }
+                a.normalize();
+                b.normalize();
+                let angle = (a * b).clamp(-1., 1.).acos();
+                vertex_faces[face[k].0].push((face_ind, angle));
+            }
+        }
+
+        let smooth_normal = |v: usize, filter: &dyn Fn(usize) -> bool| {
+            let mut normal = Vertex::default();
+            for &(face_ind, angle) in vertex_faces[v].iter() {
+                if filter(face_ind) {
+                    normal += face_normals[face_ind] * angle;
+                }
+            }
+            if normal.len() > EPS {
+                normal.normalize();
+            }
+            normal
+        };
+
+        self.normals.clear();
+        match crease_angle {
+            None => {
+                for v in 0..self.vertexes.len() {
+                    self.normals.push(smooth_normal(v, &|_| true));
+                }
+                for face in self.faces.iter_mut() {
+                    for (v, vn) in face.iter_mut() {
+                        *vn = *v;
+                    }
+                }
+            }
+            Some(crease_angle) => {
+                let min_cos = crease_angle.cos() - EPS;
+                for face_ind in 0..self.faces.len() {
+                    let face_normal = face_normals[face_ind];
+                    for k in 0..self.faces[face_ind].len() {
+                        let v = self.faces[face_ind][k].0;
+                        let normal =
+                            smooth_normal(v, &|other| face_normals[other] * face_normal >= min_cos);
+                        self.faces[face_ind][k].1 = self.normals.len();
+                        self.normals.push(normal);
                     }
-                    faces.push(face);
                 }
-                _ => {}
             }
         }
+    }
+
+    // The material library is written next to the model, with the same name
+    pub fn save(&self, path: &Path, with_material: bool) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        // faces without a material use the object colour, written last
+        let default_material = Material {
+            name: "material".to_string(),
+            ..Material::from_color(self.color.clone())
+        };
+        let materials = self
+            .materials
+            .iter()
+            .chain(std::iter::once(&default_material))
+            .collect::<Vec<_>>();
+        if with_material {
+            let mtl_path = path.with_extension("mtl");
+            let mut mtl_writer = BufWriter::new(File::create(&mtl_path)?);
+            let mtl_dir = std::path::absolute(&mtl_path)?
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            for (ind, material) in materials.iter().enumerate() {
+                Self::write_material(
+                    &mut mtl_writer,
+                    &Self::material_name(material, ind),
+                    material,
+                    &mtl_dir,
+                )?;
+            }
+            mtl_writer.flush()?;
 
-        Ok(Self::new(vertexes, faces, normals, color))
+            let mtl_name = mtl_path.file_name().unwrap_or_default().to_string_lossy();
+            writeln!(writer, "mtllib {mtl_name}")?;
+        }
+        for (ind, vertex) in self.vertexes.iter().enumerate() {
+            write!(writer, "v {} {} {}", vertex.x, vertex.y, vertex.z)?;
+            if let Some(color) = self.vertex_colors.get(ind) {
+                let unit = |c: u8| c as f64 / 255.;
+                write!(
+                    writer,
+                    " {} {} {}",
+                    unit(color.r()),
+                    unit(color.g()),
+                    unit(color.b())
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        for (u, v) in self.texcoords.iter() {
+            writeln!(writer, "vt {u} {v}")?;
+        }
+        for normal in self.normals.iter() {
+            writeln!(writer, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+        }
+        let mut current_material = None;
+        for (face_ind, face) in self.faces.iter().enumerate() {
+            if with_material {
+                let material = self
+                    .face_materials
+                    .get(face_ind)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(materials.len() - 1);
+                if current_material != Some(material) {
+                    let name = Self::material_name(materials[material], material);
+                    writeln!(writer, "usemtl {name}")?;
+                    current_material = Some(material);
+                }
+            }
+            write!(writer, "f")?;
+            for (k, (v, vn)) in face.iter().enumerate() {
+                match self.face_texcoords.get(face_ind) {
+                    Some(vts) => write!(writer, " {}/{}/{}", v + 1, vts[k] + 1, vn + 1)?,
+                    None => write!(writer, " {}//{}", v + 1, vn + 1)?,
+                }
+            }
+            writeln!(writer)?;
+        }
+
+        writer.flush()
     }
 
     pub fn color(&self) -> Color {
         self.color.clone()
     }
 
+    pub fn has_vertex_colors(&self) -> bool {
+        !self.vertex_colors.is_empty()
+    }
+
+    pub fn vertex_color(&self, index: usize) -> Option<Color> {
+        self.vertex_colors.get(index).cloned()
+    }
+
+    // Vertex colours of the object or, if there are none, the average colour of
+    // the adjacent faces taken from their materials and textures
+    pub fn bake_vertex_colors(&self, sampler: Sampler) -> Vec<Color> {
+        if self.has_vertex_colors() {
+            return self.vertex_colors.clone();
+        }
+
+        let mut sums = vec![[0.; 4]; self.nvertexes()];
+        let mut counts = vec![0; self.nvertexes()];
+        for (face_ind, face) in self.faces.iter().enumerate() {
+            let material = self.face_material(face_ind);
+            let diffuse = material.map_or(self.color(), |m| m.diffuse.clone());
+            let texture = material.and_then(|m| m.texture.clone()).or(self.texture());
+            let texcoords = self.face_texcoords(face_ind);
+            for (k, (v, _)) in face.iter().enumerate() {
+                let color = match (&texture, &texcoords) {
+                    (Some(texture), Some(texcoords)) => texture.sample(texcoords[k], sampler),
+                    _ => Color::new([255; 4]),
+                };
+                let channels = color.to_array();
+                let diffuse = diffuse.to_array();
+                for c in 0..4 {
+                    sums[*v][c] += channels[c] as f64 * diffuse[c] as f64 / 255.;
+                }
+                counts[*v] += 1;
+            }
+        }
+
+        sums.iter()
+            .zip(counts.iter())
+            .map(|(sum, &count)| match count {
+                0 => self.color(),
+                n => Color::new(sum.map(|c| (c / n as f64).round() as u8)),
+            })
+            .collect()
+    }
+
+    pub fn has_materials(&self) -> bool {
+        !self.face_materials.is_empty()
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    pub fn face_material_index(&self, index: usize) -> Option<usize> {
+        self.face_materials.get(index).copied().flatten()
+    }
+
+    pub fn face_material(&self, index: usize) -> Option<&Material> {
+        self.face_material_index(index).map(|m| &self.materials[m])
+    }
+
+    pub fn texture(&self) -> Option<SharedTexture> {
+        self.texture.clone()
+    }
+
+    pub fn set_texture(&mut self, texture: Option<SharedTexture>) {
+        self.texture = texture;
+    }
+
     pub fn nfaces(&self) -> usize {
         self.faces.len()
     }
@@ -91,6 +358,61 @@ impl Object {
         &self.center
     }
 
+    pub fn projection_center(&self) -> Vertex {
+        self.projection_center.unwrap_or(self.center)
+    }
+
+    pub fn has_custom_projection_center(&self) -> bool {
+        self.projection_center.is_some()
+    }
+
+    pub fn set_projection_center(&mut self, center: Option<Vertex>) {
+        self.projection_center = center;
+    }
+
+    pub fn landmarks(&self) -> &[usize] {
+        &self.landmarks
+    }
+
+    // A vertex is picked once, as it cannot match two points
+    pub fn add_landmark(&mut self, vertex: usize) -> bool {
+        if self.landmarks.contains(&vertex) {
+            return false;
+        }
+        self.landmarks.push(vertex);
+
+        true
+    }
+
+    pub fn pop_landmark(&mut self) -> Option<usize> {
+        self.landmarks.pop()
+    }
+
+    pub fn clear_landmarks(&mut self) {
+        self.landmarks.clear();
+    }
+
+    pub fn has_mask(&self) -> bool {
+        !self.mask.is_empty()
+    }
+
+    // 1 for every vertex until the mask is painted
+    pub fn mask_weight(&self, vertex: usize) -> f64 {
+        self.mask.get(vertex).copied().unwrap_or(1.)
+    }
+
+    // The first stroke starts from a mask of zeros, so only the painted part morphs
+    pub fn paint_mask(&mut self, vertex: usize, weight: f64) {
+        if self.mask.is_empty() {
+            self.mask = vec![0.; self.vertexes.len()];
+        }
+        self.mask[vertex] = weight.clamp(0., 1.);
+    }
+
+    pub fn clear_mask(&mut self) {
+        self.mask.clear();
+    }
+
     pub fn face_coords(&self, index: usize) -> Vec<Vertex> {
         self.faces[index]
             .iter()
@@ -109,6 +431,24 @@ impl Object {
         self.faces[index].clone()
     }
 
+    pub fn has_texcoords(&self) -> bool {
+        !self.face_texcoords.is_empty()
+    }
+
+    pub fn face_texcoords(&self, index: usize) -> Option<Vec<TexCoord>> {
+        self.face_texcoords
+            .get(index)
+            .map(|face| face.iter().map(|&vt| self.texcoords[vt]).collect())
+    }
+
+    pub fn face_texcoord_indexes(&self, index: usize) -> Option<Vec<usize>> {
+        self.face_texcoords.get(index).cloned()
+    }
+
+    pub fn texcoord(&self, index: usize) -> TexCoord {
+        self.texcoords[index]
+    }
+
     pub fn nvertexes(&self) -> usize {
         self.vertexes.len()
     }
@@ -127,6 +467,9 @@ impl Object {
 
     pub fn mov(&mut self, delta: Vertex) {
         self.center.mov(delta);
+        if let Some(center) = &mut self.projection_center {
+            center.mov(delta);
+        }
         for vertex in self.vertexes.iter_mut() {
             vertex.mov(delta);
         }
@@ -139,6 +482,9 @@ impl Object {
         for vertex in self.vertexes.iter_mut() {
             vertex.scale(&self.center, k);
         }
+        if let Some(center) = &mut self.projection_center {
+            center.scale(&self.center, k);
+        }
     }
 
     pub fn rotate(&mut self, angles: Vertex) {
@@ -175,5 +521,108 @@ impl Object {
             vertex.z = z2;
             // vertex.rotate(angles);
         }
+        if let Some(center) = &mut self.projection_center {
+            center.rotate(angles);
+        }
+    }
+}
+
+impl Object {
+    // Missing libraries are skipped, the faces then use the object colour
+    fn load_materials(dir: &Path, libs: &[String]) -> Result<Vec<Material>, ObjError> {
+        let mut materials = Vec::new();
+        for lib in libs {
+            let path = dir.join(lib);
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let lib_dir = path.parent().unwrap_or(dir);
+            let lib_materials =
+                MtlParser::parse(BufReader::new(file), lib_dir).map_err(|e| match e {
+                    ObjError::Parse { line, kind } => ObjError::Mtl {
+                        filename: lib.clone(),
+                        line,
+                        kind,
+                    },
+                    e => e,
+                })?;
+            materials.extend(lib_materials);
+        }
+
+        Ok(materials)
+    }
+
+    fn material_name(material: &Material, index: usize) -> String {
+        if material.name.is_empty() {
+            format!("material{index}")
+        } else {
+            material.name.clone()
+        }
+    }
+
+    // Textures are referred to relative to `dir` of the library if they lie in it
+    fn write_material<W: Write>(
+        writer: &mut W,
+        name: &str,
+        material: &Material,
+        dir: &Path,
+    ) -> io::Result<()> {
+        let unit = |c: u8| c as f64 / 255.;
+        let rgb = |c: &Color| format!("{} {} {}", unit(c.r()), unit(c.g()), unit(c.b()));
+
+        writeln!(writer, "newmtl {name}")?;
+        writeln!(writer, "Ka {}", rgb(&material.ambient))?;
+        writeln!(writer, "Kd {}", rgb(&material.diffuse))?;
+        writeln!(writer, "Ks {}", rgb(&material.specular))?;
+        writeln!(writer, "Ns {}", material.shininess)?;
+        writeln!(writer, "d {}", unit(material.diffuse.a()))?;
+        if let Some(path) = material.texture.as_ref().and_then(|t| t.path()) {
+            let path = path.strip_prefix(dir).unwrap_or(path);
+            writeln!(writer, "map_Kd {}", path.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn texture_survives_saving() {
+        let dir = std::env::temp_dir().join(format!("morphing_object_{}", std::process::id()));
+        let copies = dir.join("copies");
+        fs::create_dir_all(&copies).unwrap();
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 255, 0, 255]))
+            .save(dir.join("green.png"))
+            .unwrap();
+        fs::write(dir.join("box.mtl"), "newmtl skin\nmap_Kd green.png\n").unwrap();
+        fs::write(
+            dir.join("box.obj"),
+            "mtllib box.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nvt 0 0\n\
+             usemtl skin\nf 1/1 3/1 2/1\nf 1/1 2/1 4/1\nf 2/1 3/1 4/1\nf 1/1 4/1 3/1\n",
+        )
+        .unwrap();
+
+        let color = Color::new([255; 4]);
+        let object = Object::load(&dir.join("box.obj").to_string_lossy(), color.clone()).unwrap();
+        // next to the texture and in another directory
+        object.save(&dir.join("same.obj"), true).unwrap();
+        object.save(&copies.join("other.obj"), true).unwrap();
+        let same_mtl = fs::read_to_string(dir.join("same.mtl")).unwrap();
+        let reloaded = [dir.join("same.obj"), copies.join("other.obj")]
+            .map(|path| Object::load(&path.to_string_lossy(), color.clone()).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(same_mtl.contains("map_Kd green.png\n"));
+        for object in reloaded {
+            let texture = object.face_material(0).unwrap().texture.clone().unwrap();
+            assert_eq!(texture.sample((0.5, 0.5), Sampler::default()).g(), 255);
+        }
     }
 }
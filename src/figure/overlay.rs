@@ -0,0 +1,304 @@
+use crate::{parallel, EPS};
+
+use super::{
+    arc::{Arc, ArcIntersectionResult},
+    arc_grid::ArcGrid,
+    edge::Edge,
+    edge_set::EdgeSet,
+    graph::Graph,
+    morph_error::MorphError,
+    progress::{Phase, Progress},
+    projection::Projection,
+    vertex::Vertex,
+};
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::f64::consts::PI;
+
+// Subdivision of the sphere by the edges of several projected models, every
+// model can be placed on it, so they all share the same mesh
+pub struct Overlay {
+    vertexes: Vec<SphereVertex>,
+    edges: EdgeSet,
+}
+
+pub struct SphereVertex {
+    pub vertex: Vertex,
+    pub origin: Option<(usize, usize)>, // (model, vertex) unless it is an intersection of edges
+}
+
+impl Overlay {
+    // The edges of every next model are cut by the edges laid before
+    pub fn new(projs: &[&Projection], progress: &Progress) -> Result<Self, MorphError> {
+        let mut sphere_vertexes = Vec::new();
+        let mut offsets = Vec::with_capacity(projs.len());
+        for (model, proj) in projs.iter().enumerate() {
+            offsets.push(sphere_vertexes.len());
+            for i in 0..proj.nvertexes() {
+                sphere_vertexes.push(SphereVertex {
+                    vertex: proj.sphere_vertex(i),
+                    origin: Some((model, i)),
+                });
+            }
+        }
+
+        let arc_len = |proj: &Projection| {
+            proj.edges_iter()
+                .map(|e| (proj.sphere_vertex(e.from) - proj.sphere_vertex(e.to)).len())
+                .collect::<Vec<_>>()
+        };
+        let arcs_len = projs
+            .iter()
+            .flat_map(|proj| arc_len(proj))
+            .collect::<Vec<_>>();
+        let mut edges = EdgeSet::new();
+        let mut grid = ArcGrid::new(
+            projs[0].radius(),
+            arcs_len.iter().sum::<f64>() / arcs_len.len().max(1) as f64,
+        );
+        for src_edge in projs[0].edges_iter() {
+            edges.insert(src_edge);
+            grid.insert(
+                src_edge,
+                sphere_vertexes[src_edge.from].vertex,
+                sphere_vertexes[src_edge.to].vertex,
+            );
+        }
+        progress.start(
+            Phase::Overlay,
+            projs[1..]
+                .iter()
+                .map(|proj| proj.edges_iter().count())
+                .sum(),
+        );
+        for (proj, &offset) in projs.iter().zip(&offsets).skip(1) {
+            for dst_edge in proj.edges_iter() {
+                if progress.is_cancelled() {
+                    return Err(MorphError::Cancelled);
+                }
+                progress.advance(1);
+                let dst_edge = Edge::new(dst_edge.from + offset, dst_edge.to + offset);
+                Self::cut_in(&mut sphere_vertexes, &mut edges, &mut grid, dst_edge);
+            }
+        }
+
+        Ok(Self {
+            vertexes: sphere_vertexes,
+            edges,
+        })
+    }
+
+    pub fn vertexes(&self) -> &[SphereVertex] {
+        &self.vertexes
+    }
+
+    // Faces of the subdivision split into triangles, fails with the vertex
+    // whose edges do not close into faces
+    pub fn triangles(&self) -> Result<Vec<Vec<usize>>, usize> {
+        let verts = self.vertexes.iter().map(|v| v.vertex).collect::<Vec<_>>();
+        let faces = Self::resolve_faces(&verts, &self.edges)?;
+
+        let mut triangle_faces = Vec::new();
+        let mut set = BTreeSet::<Vec<usize>>::new();
+        for f in faces.into_iter() {
+            if f.len() > 3 {
+                for i in 1..f.len() - 1 {
+                    let mut tri = vec![f[0], f[i], f[i + 1]];
+                    tri.sort();
+                    if f[0] == f[i] || f[0] == f[i + 1] {
+                        continue;
+                    }
+                    if set.insert(tri.clone()) {
+                        triangle_faces.push(tri);
+                    }
+                }
+            } else {
+                let mut tri = f;
+                tri.sort();
+                if set.insert(tri.clone()) {
+                    triangle_faces.push(tri);
+                }
+            }
+        }
+
+        Ok(triangle_faces)
+    }
+}
+
+impl Overlay {
+    // Splits the laid edges crossed by `dst_edge` and lays it in pieces
+    // between the crossings
+    fn cut_in(
+        sphere_vertexes: &mut Vec<SphereVertex>,
+        edges: &mut EdgeSet,
+        grid: &mut ArcGrid,
+        dst_edge: Edge,
+    ) {
+        let v1 = sphere_vertexes[dst_edge.from].vertex;
+        let v2 = sphere_vertexes[dst_edge.to].vertex;
+        let dst_arc = Arc::new(v1, v2, dst_edge.from, dst_edge.to);
+        let mut intersections = vec![(0., dst_edge.from), (1., dst_edge.to)];
+        for src_edge in grid.candidates(edges, v1, v2).iter() {
+            let u1 = sphere_vertexes[src_edge.from].vertex;
+            let u2 = sphere_vertexes[src_edge.to].vertex;
+            let src_arc = Arc::new(u1, u2, src_edge.from, src_edge.to);
+            match Arc::intersect(&src_arc, &dst_arc) {
+                ArcIntersectionResult::T1(index, k) => intersections.push((k, index)),
+                ArcIntersectionResult::T2(index, ..) => {
+                    edges.remove(src_edge);
+                    Self::add_edge(edges, grid, sphere_vertexes, src_edge.from, index);
+                    Self::add_edge(edges, grid, sphere_vertexes, src_edge.to, index);
+                }
+                ArcIntersectionResult::X(vertex, k) => {
+                    let id = sphere_vertexes.len();
+                    sphere_vertexes.push(SphereVertex {
+                        vertex,
+                        origin: None,
+                    });
+                    edges.remove(src_edge);
+                    Self::add_edge(edges, grid, sphere_vertexes, src_edge.from, id);
+                    Self::add_edge(edges, grid, sphere_vertexes, src_edge.to, id);
+                    intersections.push((k, id));
+                }
+                ArcIntersectionResult::I((id1, k1), (id2, k2)) => {
+                    edges.remove(src_edge);
+                    if k1 > 0. {
+                        intersections.push((k1, id1));
+                    } else if k1 < 0. {
+                        Self::add_edge(edges, grid, sphere_vertexes, id1, intersections[0].1);
+                    }
+
+                    if k2 < 1. {
+                        intersections.push((k2, id2));
+                    } else if k2 > 1. {
+                        Self::add_edge(edges, grid, sphere_vertexes, id2, intersections[1].1);
+                    }
+                }
+                ArcIntersectionResult::L(id1, id2) => {
+                    if id2 == dst_edge.from {
+                        intersections[0].1 = id1;
+                    } else if id2 == dst_edge.to {
+                        intersections[1].1 = id1;
+                    }
+                }
+                ArcIntersectionResult::S => return,
+                ArcIntersectionResult::N => {}
+            }
+        }
+
+        intersections.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        for i in 0..intersections.len() - 1 {
+            Self::add_edge(
+                edges,
+                grid,
+                sphere_vertexes,
+                intersections[i].1,
+                intersections[i + 1].1,
+            );
+        }
+    }
+
+    fn add_edge(
+        edges: &mut EdgeSet,
+        grid: &mut ArcGrid,
+        sphere_vertexes: &[SphereVertex],
+        from: usize,
+        to: usize,
+    ) {
+        if edges.add(from, to) {
+            grid.insert(
+                &Edge::new(from, to),
+                sphere_vertexes[from].vertex,
+                sphere_vertexes[to].vertex,
+            );
+        }
+    }
+
+    // Fails with the vertex whose edges do not close into faces
+    fn resolve_faces(verts: &[Vertex], edges: &EdgeSet) -> Result<Vec<Vec<usize>>, usize> {
+        let n = verts.len();
+        let mut graph = Graph::new(verts);
+        for e in edges.iter() {
+            graph.add_pair(e.from, e.to);
+        }
+
+        // neighbours are ordered around each vertex independently, only the
+        // linking of the `Rc` edges has to stay on this thread
+        let neighbors = (0..n)
+            .map(|i| {
+                graph
+                    .neighbors(i)
+                    .map(|e| e.borrow().to)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let orders = parallel::map_range(n, |i| Self::neighbors_order(verts, i, &neighbors[i]));
+        for (i, order) in orders.iter().enumerate() {
+            let adj_edges = graph.neighbors(i).collect::<Vec<_>>();
+            let m = order.len();
+            for j in 0..m {
+                let k = if j == m - 1 { 0 } else { j + 1 };
+                adj_edges[order[j]].borrow_mut().next = std::rc::Rc::downgrade(adj_edges[order[k]]);
+            }
+        }
+
+        let mut faces = Vec::new();
+        for i in 0..n {
+            for e in graph.neighbors(i) {
+                let mut e = e.clone();
+                let mut one_face = Vec::new();
+                while !e.borrow().visited {
+                    let p = e.borrow().to;
+                    one_face.push(p);
+                    e.borrow_mut().visited = true;
+                    let o = e.borrow().oppo.upgrade().ok_or(p)?;
+                    let n = o.borrow().next.upgrade().ok_or(p)?;
+                    e = n;
+                }
+                if one_face.len() > 2 {
+                    faces.push(one_face);
+                }
+            }
+        }
+
+        Ok(faces)
+    }
+
+    // Positions in `neighbors` sorted by the angle around `verts[i]`
+    fn neighbors_order(verts: &[Vertex], i: usize, neighbors: &[usize]) -> Vec<usize> {
+        if neighbors.is_empty() {
+            return Vec::new();
+        }
+        let v = verts[i];
+        let v_len = v.len();
+        let first = verts[neighbors[0]];
+        let mut first_dir = first - v * (v * first / v_len);
+        first_dir.normalize();
+        let mut angles = neighbors
+            .iter()
+            .enumerate()
+            .map(|(pos, &to)| {
+                let p = verts[to];
+                let mut dir = p - v * (v * p / v_len);
+                dir.normalize();
+                let norm = first_dir ^ dir;
+                let cos = first_dir * dir;
+                let mut angle = if (cos - 1.).abs() < EPS {
+                    0.
+                } else if (cos + 1.).abs() < EPS {
+                    PI
+                } else {
+                    cos.acos()
+                };
+                if v * norm < -EPS {
+                    angle = -angle;
+                }
+                (angle, pos)
+            })
+            .collect::<Vec<(f64, usize)>>();
+        angles.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        angles.into_iter().map(|(_, pos)| pos).collect()
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    StarCheck,
+    Projection,
+    Alignment,
+    Overlay,
+    BackProjection,
+    FaceResolution,
+    Trajectory,
+}
+
+impl Phase {
+    const ALL: [Phase; 7] = [
+        Phase::StarCheck,
+        Phase::Projection,
+        Phase::Alignment,
+        Phase::Overlay,
+        Phase::BackProjection,
+        Phase::FaceResolution,
+        Phase::Trajectory,
+    ];
+}
+
+// Shared between the thread preparing a morph and the one showing it
+#[derive(Default)]
+pub struct Progress {
+    phase: AtomicUsize,
+    done: AtomicUsize,
+    total: AtomicUsize,
+    is_cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&self, phase: Phase, total: usize) {
+        self.done.store(0, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+        self.phase.store(phase as usize, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, n: usize) {
+        self.done.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn phase(&self) -> Phase {
+        Phase::ALL[self.phase.load(Ordering::Relaxed)]
+    }
+
+    // Share of the current phase which is done, from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.;
+        }
+
+        (self.done.load(Ordering::Relaxed) as f32 / total as f32).min(1.)
+    }
+
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+}
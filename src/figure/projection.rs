@@ -1,27 +1,100 @@
-use std::collections::btree_set::Iter;
+use std::collections::{btree_set::Iter, BTreeMap, BTreeSet};
 
 use crate::color::Color;
+use crate::texture::{Sampler, SharedTexture};
+use crate::EPS;
 
-use super::{edge::Edge, edge_set::EdgeSet, object::Object, triangle::Triangle, vertex::Vertex};
+use super::{
+    bvh::Bvh,
+    edge::Edge,
+    edge_set::EdgeSet,
+    harmonic::HarmonicMap,
+    material::Material,
+    matrix::{self, Matrix},
+    morph_error::MeshErrorKind,
+    object::Object,
+    sphere_warp::SphereWarp,
+    triangle::Triangle,
+    vertex::{TexCoord, Vertex},
+};
 
+// How the vertexes are placed on the sphere
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mapping {
+    // along the rays from the projection center, needs a star-shaped model
+    #[default]
+    Central,
+    // harmonic embedding of any closed genus 0 model
+    Harmonic,
+}
+
+// Point where a ray from the center hits the object surface
+pub struct SurfacePoint {
+    pub vertex: Vertex,
+    pub normal: Vertex,
+    pub texcoord: Option<TexCoord>,
+    pub color: Color,
+    pub mask: f64,
+    pub face: usize,
+}
+
+#[derive(Clone)]
 pub struct Projection {
+    mapping: Mapping,
+    is_warped: bool, // rays are cast against the sphere as for the harmonic mapping
     radius: f64,
     sphere_vertexes: Vec<Vertex>,
     edges: EdgeSet,
     vn: Vec<usize>,
+    vt: Vec<Option<usize>>,
+    vertex_colors: Vec<Color>, // baked if the object has no vertex colours
+    bvh: Bvh,
     object: Object,
 }
 
 impl Projection {
     pub fn new(object: Object, radius: f64) -> Self {
-        let center = *object.center();
-        let nfaces = object.nfaces();
+        let center = object.projection_center();
         let mut sphere_vertexes = vec![];
-        let mut edges = EdgeSet::new();
         for vertex in object.vertexes_iter() {
             sphere_vertexes.push(vertex.project_to_sphere(center, radius));
         }
+
+        Self::with_sphere(object, radius, Mapping::Central, sphere_vertexes)
+    }
+
+    pub fn harmonic(object: Object, radius: f64) -> Result<Self, MeshErrorKind> {
+        let sphere_vertexes = HarmonicMap::new(&object)?.embed(&object, radius)?;
+
+        Ok(Self::with_sphere(
+            object,
+            radius,
+            Mapping::Harmonic,
+            sphere_vertexes,
+        ))
+    }
+
+    pub fn with_mapping(
+        object: Object,
+        radius: f64,
+        mapping: Mapping,
+    ) -> Result<Self, MeshErrorKind> {
+        match mapping {
+            Mapping::Central => Ok(Self::new(object, radius)),
+            Mapping::Harmonic => Self::harmonic(object, radius),
+        }
+    }
+
+    fn with_sphere(
+        object: Object,
+        radius: f64,
+        mapping: Mapping,
+        sphere_vertexes: Vec<Vertex>,
+    ) -> Self {
+        let nfaces = object.nfaces();
+        let mut edges = EdgeSet::new();
         let mut vn = vec![0; object.nvertexes()];
+        let mut vt = vec![None; object.nvertexes()];
         for face_ind in 0..nfaces {
             let face = object.face_indexes(face_ind);
             edges.add(face[0].0, face[1].0);
@@ -31,21 +104,82 @@ impl Projection {
             vn[face[0].0] = face[0].1;
             vn[face[1].0] = face[1].1;
             vn[face[2].0] = face[2].1;
+
+            if let Some(face_vt) = object.face_texcoord_indexes(face_ind) {
+                vt[face[0].0] = Some(face_vt[0]);
+                vt[face[1].0] = Some(face_vt[1]);
+                vt[face[2].0] = Some(face_vt[2]);
+            }
         }
 
+        let vertex_colors = object.bake_vertex_colors(Sampler::default());
+        let bvh = match mapping {
+            Mapping::Central => Bvh::from_object(&object),
+            Mapping::Harmonic => Self::chord_bvh(&object, &sphere_vertexes),
+        };
+
         Self {
+            mapping,
+            is_warped: false,
             radius,
+            vertex_colors,
             sphere_vertexes,
             vn,
+            vt,
             edges,
+            bvh,
             object,
         }
     }
 
+    pub fn mapping(&self) -> Mapping {
+        self.mapping
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
     pub fn color(&self) -> Color {
         self.object.color()
     }
 
+    pub fn texture(&self) -> Option<SharedTexture> {
+        self.object.texture()
+    }
+
+    pub fn has_vertex_colors(&self) -> bool {
+        self.object.has_vertex_colors()
+    }
+
+    pub fn vertex_color(&self, index: usize) -> Color {
+        self.vertex_colors[index].clone()
+    }
+
+    pub fn has_mask(&self) -> bool {
+        self.object.has_mask()
+    }
+
+    pub fn mask_weight(&self, index: usize) -> f64 {
+        self.object.mask_weight(index)
+    }
+
+    pub fn has_materials(&self) -> bool {
+        self.object.has_materials()
+    }
+
+    pub fn face_material_index(&self, face: usize) -> Option<usize> {
+        self.object.face_material_index(face)
+    }
+
+    // Faces without a material get the object colour
+    pub fn face_material(&self, face: usize) -> Material {
+        match self.object.face_material(face) {
+            Some(material) => material.clone(),
+            None => Material::from_color(self.color()),
+        }
+    }
+
     pub fn sphere_vertex(&self, index: usize) -> Vertex {
         self.sphere_vertexes[index]
     }
@@ -54,6 +188,10 @@ impl Projection {
         self.object.normal(self.vn[index])
     }
 
+    pub fn texcoord(&self, index: usize) -> Option<TexCoord> {
+        self.vt[index].map(|vt| self.object.texcoord(vt))
+    }
+
     pub fn vertex(&self, index: usize) -> Vertex {
         self.object.vertex(index)
     }
@@ -70,18 +208,259 @@ impl Projection {
         self.object.center()
     }
 
-    pub fn project_from_sphere(&self, v: Vertex) -> Result<(Vertex, Vertex), ()> {
-        // vertex and normal
-        let center = *self.object.center();
+    pub fn projection_center(&self) -> Vertex {
+        self.object.projection_center()
+    }
+
+    // Defects which make the model impossible to morph
+    pub fn check(&self) -> Result<(), MeshErrorKind> {
+        for ind in 0..self.object.nvertexes() {
+            let v = self.object.vertex(ind);
+            if !(v.x.is_finite() && v.y.is_finite() && v.z.is_finite()) {
+                return Err(MeshErrorKind::Numeric(ind));
+            }
+        }
+
+        // the area is compared to the size of the model, so small models are not rejected
+        let (mut min, mut max) = (
+            Vertex::new(f64::MAX, f64::MAX, f64::MAX),
+            Vertex::new(f64::MIN, f64::MIN, f64::MIN),
+        );
+        for v in self.object.vertexes_iter() {
+            min = Vertex::new(min.x.min(v.x), min.y.min(v.y), min.z.min(v.z));
+            max = Vertex::new(max.x.max(v.x), max.y.max(v.y), max.z.max(v.z));
+        }
+        let min_area = EPS * (max - min).len2();
+
+        let mut edge_faces = BTreeMap::new();
         for ind in 0..self.object.nfaces() {
             let coords = self.object.face_coords(ind);
-            let tri = Triangle::new(coords[0], coords[1], coords[2]);
-            if let Some(int) = tri.intersect(center, center + v) {
-                let normal = tri.normal_inside(int, self.object.face_normals(ind));
-                return Ok((int, normal));
+            if Triangle::new(coords[0], coords[1], coords[2])
+                .normal()
+                .len()
+                <= min_area
+            {
+                return Err(MeshErrorKind::DegenerateFace(ind));
+            }
+            let face = self.object.face_indexes(ind);
+            for (k, l) in [(0, 1), (1, 2), (2, 0)] {
+                let edge = Edge::new(face[k].0, face[l].0).norm();
+                *edge_faces.entry(edge).or_insert(0) += 1;
+            }
+        }
+        if let Some((Some(edge), _)) = edge_faces.into_iter().find(|(_, count)| *count > 2) {
+            return Err(MeshErrorKind::NonManifold(edge.from, edge.to));
+        }
+
+        Ok(())
+    }
+
+    // Vertex whose projection is the closest to the direction `v`
+    pub fn nearest_vertex(&self, v: Vertex) -> usize {
+        let mut best = (f64::MIN, 0);
+        for (ind, u) in self.sphere_vertexes.iter().enumerate() {
+            let cos = (*u * v) / (u.len() * v.len());
+            if cos > best.0 {
+                best = (cos, ind);
             }
         }
 
-        Err(())
+        best.1
+    }
+
+    // Warps the sphere so that the own vertex of each pair `(other, own)` comes
+    // to the projection of the vertex of `other`. Fails if the warp folds the sphere.
+    pub fn align_to(
+        &mut self,
+        other: &Projection,
+        pairs: &[(usize, usize)],
+    ) -> Result<(), MeshErrorKind> {
+        // a vertex picked twice would have to go to two places
+        let mut used = (BTreeSet::new(), BTreeSet::new());
+        let pairs = pairs
+            .iter()
+            .filter(|&&(a, b)| used.0.insert(a) && used.1.insert(b))
+            .collect::<Vec<_>>();
+        if pairs.is_empty() {
+            return Ok(());
+        }
+
+        let to = pairs
+            .iter()
+            .map(|&&(a, _)| other.sphere_vertex(a))
+            .collect::<Vec<_>>();
+        let from = pairs
+            .iter()
+            .map(|&&(_, b)| self.sphere_vertex(b))
+            .collect::<Vec<_>>();
+        let warp = SphereWarp::new(self.radius, &from, &to);
+        let mut warped = self
+            .sphere_vertexes
+            .iter()
+            .map(|&v| warp.apply(v))
+            .collect::<Vec<_>>();
+        // the overlay needs the matched vertexes to coincide exactly, not up to rounding
+        for (&&(_, b), &v) in pairs.iter().zip(&to) {
+            warped[b] = v;
+        }
+        let volume = |sphere: &[Vertex], ind: usize| {
+            let face = self.object.face_indexes(ind);
+            sphere[face[0].0] * (sphere[face[1].0] ^ sphere[face[2].0])
+        };
+        if let Some(face) = (0..self.object.nfaces()).find(|&ind| {
+            let before = volume(&self.sphere_vertexes, ind);
+            before.abs() > EPS && volume(&warped, ind) * before.signum() <= 0.
+        }) {
+            return Err(MeshErrorKind::Warp(face));
+        }
+
+        self.set_sphere(warped);
+
+        Ok(())
+    }
+
+    // Turns the vertexes on the sphere, the model itself stays in place
+    pub fn rotate_sphere(&mut self, rotation: &Matrix) {
+        let turned = self
+            .sphere_vertexes
+            .iter()
+            .map(|&v| matrix::transform(rotation, v))
+            .collect();
+        self.set_sphere(turned);
+    }
+
+    pub fn project_from_sphere(&self, v: Vertex) -> Result<SurfacePoint, MeshErrorKind> {
+        let origin = if self.casts_on_sphere() {
+            Vertex::new(0., 0., 0.)
+        } else {
+            self.object.projection_center()
+        };
+        // a ray through a shared edge hits several faces, the first one is taken
+        let mut hit: Option<(usize, Vertex)> = None;
+        self.bvh.query(origin, v, |ind| {
+            if hit.as_ref().is_some_and(|(first, _)| *first < ind) {
+                return;
+            }
+            if let Some(int) = self.ray_triangle(ind).intersect(origin, origin + v) {
+                hit = Some((ind, int));
+            }
+        });
+        let Some((ind, int)) = hit else {
+            return Err(MeshErrorKind::NotStarShaped(self.nearest_vertex(v)));
+        };
+        let coords = self.object.face_coords(ind);
+        let tri = Triangle::new(coords[0], coords[1], coords[2]);
+        // the same barycentric coordinates on the face of the model
+        let int = if self.casts_on_sphere() {
+            let (t1, t2, t3) = self.ray_triangle(ind).barycentric(int);
+            coords[0] * t1 + coords[1] * t2 + coords[2] * t3
+        } else {
+            int
+        };
+
+        let normal = tri.normal_inside(int, self.object.face_normals(ind));
+        let texcoord = self
+            .object
+            .face_texcoords(ind)
+            .map(|texcoords| tri.texcoord_inside(int, texcoords));
+        let face = self.object.face_indexes(ind);
+        let colors = face
+            .iter()
+            .map(|(v, _)| self.vertex_colors[*v].clone())
+            .collect();
+        let color = tri.color_inside(int, colors);
+        let mask = tri.weight_inside(int, [0, 1, 2].map(|k| self.mask_weight(face[k].0)));
+
+        Ok(SurfacePoint {
+            vertex: int,
+            normal,
+            texcoord,
+            color,
+            mask,
+            face: ind,
+        })
+    }
+}
+
+impl Projection {
+    // Triangle of the face which the rays from the sphere are cast against
+    fn ray_triangle(&self, face: usize) -> Triangle {
+        if self.casts_on_sphere() {
+            let face = self.object.face_indexes(face);
+            Triangle::new(
+                self.sphere_vertexes[face[0].0],
+                self.sphere_vertexes[face[1].0],
+                self.sphere_vertexes[face[2].0],
+            )
+        } else {
+            let coords = self.object.face_coords(face);
+            Triangle::new(coords[0], coords[1], coords[2])
+        }
+    }
+
+    fn set_sphere(&mut self, sphere_vertexes: Vec<Vertex>) {
+        self.bvh = Self::chord_bvh(&self.object, &sphere_vertexes);
+        self.sphere_vertexes = sphere_vertexes;
+        self.is_warped = true;
+    }
+
+    // The model surface is no longer behind the vertexes on the sphere
+    fn casts_on_sphere(&self) -> bool {
+        self.mapping == Mapping::Harmonic || self.is_warped
+    }
+
+    // Flat triangles between the vertexes on the sphere, for rays from the origin
+    fn chord_bvh(object: &Object, sphere_vertexes: &[Vertex]) -> Bvh {
+        Bvh::new(
+            &(0..object.nfaces())
+                .map(|ind| {
+                    let face = object.face_indexes(ind);
+                    [0, 1, 2].map(|k| sphere_vertexes[face[k].0])
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron(scale: f64, top: Vertex) -> Projection {
+        let vertexes = [
+            Vertex::new(0., 0., 0.),
+            Vertex::new(1., 0., 0.),
+            Vertex::new(0., 1., 0.),
+            top,
+        ]
+        .map(|v| v * scale)
+        .to_vec();
+        let faces = [[0, 2, 1], [0, 1, 3], [1, 2, 3], [0, 3, 2]]
+            .iter()
+            .map(|face| face.iter().map(|&v| (v, v)).collect())
+            .collect();
+        let mut object = Object::new(vertexes, faces, Vec::new(), Color::new([255; 4]));
+        object.compute_normals(None);
+
+        Projection::new(object, 100.)
+    }
+
+    #[test]
+    fn degenerate_face_is_relative_to_the_size() {
+        let top = Vertex::new(0., 0., 1.);
+        assert_eq!(tetrahedron(1., top).check(), Ok(()));
+        assert_eq!(tetrahedron(1e-6, top).check(), Ok(()));
+        assert_eq!(tetrahedron(1e6, top).check(), Ok(()));
+
+        // the top on the middle of an edge of the base flattens the face over that edge
+        let top = Vertex::new(0.5, 0., 0.);
+        assert_eq!(
+            tetrahedron(1e-6, top).check(),
+            Err(MeshErrorKind::DegenerateFace(1))
+        );
+        assert_eq!(
+            tetrahedron(1e6, top).check(),
+            Err(MeshErrorKind::DegenerateFace(1))
+        );
     }
 }
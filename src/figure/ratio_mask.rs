@@ -0,0 +1,189 @@
+use super::vertex::Vertex;
+use crate::EPS;
+
+// Part of the model which morphs, around the center of the model the morph starts from
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Region {
+    #[default]
+    Whole,
+    Sphere {
+        center: Vertex,
+        radius: f64,
+    },
+    // the side the normal points to
+    HalfSpace {
+        point: Vertex,
+        normal: Vertex,
+    },
+}
+
+// What happens to the vertexes outside the region
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Timing {
+    // they stay in place
+    #[default]
+    Limit,
+    // they make the whole way too, but start later by up to this share of the morph
+    Delay(f64),
+}
+
+// Own share of the morph for each vertex: the weight of a vertex falls from 1
+// inside the region to 0 at `falloff` outside it and is multiplied by the mask
+// painted on the model if `is_painted`
+#[derive(Clone, Copy, PartialEq)]
+pub struct RatioMask {
+    pub region: Region,
+    pub falloff: f64,
+    pub is_painted: bool,
+    pub timing: Timing,
+}
+
+impl Default for RatioMask {
+    fn default() -> Self {
+        Self {
+            region: Region::Whole,
+            falloff: 0.5,
+            is_painted: false,
+            timing: Timing::Limit,
+        }
+    }
+}
+
+impl RatioMask {
+    // Every vertex follows the ratio of the whole morph
+    pub fn is_uniform(&self) -> bool {
+        self.region == Region::Whole && !self.is_painted
+    }
+
+    // `v` is relative to the center of the model, `painted` is its painted weight
+    pub fn weight(&self, v: Vertex, painted: f64) -> f64 {
+        let distance = match self.region {
+            Region::Whole => 0.,
+            Region::Sphere { center, radius } => (v - center).len() - radius,
+            Region::HalfSpace { point, normal } => {
+                let len = normal.len();
+                if len < EPS {
+                    0.
+                } else {
+                    (point - v) * normal / len
+                }
+            }
+        };
+        let weight = if distance <= 0. {
+            1.
+        } else if distance >= self.falloff {
+            0.
+        } else {
+            // smoothstep, so the edge of the region does not crease
+            let t = 1. - distance / self.falloff;
+            t * t * (3. - 2. * t)
+        };
+
+        if self.is_painted {
+            weight * painted.clamp(0., 1.)
+        } else {
+            weight
+        }
+    }
+
+    // Share of the way made by a vertex of weight `weight` at `ratio` of the whole morph
+    pub fn local_ratio(&self, ratio: f64, weight: f64) -> f64 {
+        match self.timing {
+            Timing::Limit => ratio * weight,
+            Timing::Delay(delay) => {
+                let delay = delay.clamp(0., 1.);
+                let start = delay * (1. - weight);
+                if 1. - delay < EPS {
+                    return if ratio >= start { 1. } else { 0. };
+                }
+                ((ratio - start) / (1. - delay)).clamp(0., 1.)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask(region: Region, timing: Timing) -> RatioMask {
+        RatioMask {
+            region,
+            timing,
+            ..RatioMask::default()
+        }
+    }
+
+    #[test]
+    fn smoothstep_over_falloff() {
+        let sphere = Region::Sphere {
+            center: Vertex::new(0., 0., 0.),
+            radius: 1.,
+        };
+        let mask = mask(sphere, Timing::Limit);
+        let weight = |x: f64| mask.weight(Vertex::new(x, 0., 0.), 1.);
+
+        assert_eq!(weight(0.5), 1.);
+        assert_eq!(weight(1.), 1.);
+        assert_eq!(weight(1.25), 0.5);
+        assert_eq!(weight(1.5), 0.);
+        assert_eq!(weight(2.), 0.);
+        // flat at both ends of the falloff, so the edge does not crease
+        let d = 1e-4;
+        assert!((1. - weight(1. + d)) < 1e-6);
+        assert!(weight(1.5 - d) < 1e-6);
+        let inner = weight(1.125);
+        let outer = weight(1.375);
+        assert!(inner > 0.75 && outer < 0.25);
+        assert!((inner + outer - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn half_space_side() {
+        let half_space = Region::HalfSpace {
+            point: Vertex::new(0., 0., 1.),
+            normal: Vertex::new(0., 0., 2.),
+        };
+        let mask = mask(half_space, Timing::Limit);
+
+        assert_eq!(mask.weight(Vertex::new(3., -2., 1.5), 1.), 1.);
+        assert_eq!(mask.weight(Vertex::new(3., -2., 0.75), 1.), 0.5);
+        assert_eq!(mask.weight(Vertex::new(3., -2., 0.), 1.), 0.);
+    }
+
+    #[test]
+    fn zero_normal_is_whole() {
+        let half_space = Region::HalfSpace {
+            point: Vertex::new(0., 0., 1.),
+            normal: Vertex::new(0., 0., 0.),
+        };
+        let mask = mask(half_space, Timing::Limit);
+
+        for z in [-5., 0., 5.] {
+            assert_eq!(mask.weight(Vertex::new(0., 0., z), 1.), 1.);
+        }
+    }
+
+    #[test]
+    fn full_delay_is_a_step() {
+        let mask = mask(Region::Whole, Timing::Delay(1.));
+
+        assert_eq!(mask.local_ratio(0.5, 0.25), 0.);
+        assert_eq!(mask.local_ratio(0.5, 0.75), 1.);
+        assert_eq!(mask.local_ratio(0., 1.), 1.);
+        assert_eq!(mask.local_ratio(0.99, 0.), 0.);
+    }
+
+    #[test]
+    fn delayed_vertexes_finish() {
+        for delay in [0., 0.3, 0.5, 1.] {
+            let mask = mask(Region::Whole, Timing::Delay(delay));
+            for weight in [0., 0.2, 0.5, 1.] {
+                assert_eq!(mask.local_ratio(1., weight), 1., "{delay} {weight}");
+                if delay < 1. {
+                    assert_eq!(mask.local_ratio(0., weight), 0., "{delay} {weight}");
+                }
+            }
+        }
+    }
+}
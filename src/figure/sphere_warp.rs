@@ -0,0 +1,113 @@
+use super::{
+    matrix::{self, Matrix},
+    vertex::Vertex,
+};
+use crate::EPS;
+
+const WIDTH: f64 = 0.5; // of the Gaussian kernel, in radii
+const REGULARIZATION: f64 = 1e-9;
+
+// Smooth map of the sphere taking the points `from` to `to`: the rotation
+// fitting them best, followed by a displacement interpolated with Gaussian
+// radial basis functions and projected back onto the sphere
+pub struct SphereWarp {
+    radius: f64,
+    rotation: Matrix,
+    centers: Vec<Vertex>,
+    weights: Vec<Vertex>,
+}
+
+impl SphereWarp {
+    pub fn new(radius: f64, from: &[Vertex], to: &[Vertex]) -> Self {
+        let mut m = [[0.; 3]; 3];
+        for (&a, &b) in to.iter().zip(from) {
+            matrix::add_outer(&mut m, a, b);
+        }
+        let rotation = matrix::orthogonal_fit(m, false);
+
+        let centers = from
+            .iter()
+            .map(|&v| matrix::transform(&rotation, v))
+            .collect::<Vec<_>>();
+        let mut rows = centers
+            .iter()
+            .zip(to)
+            .map(|(&c, &t)| {
+                let kernel = centers
+                    .iter()
+                    .map(|&other| Self::kernel(radius, c - other))
+                    .collect::<Vec<_>>();
+                (kernel, t - c)
+            })
+            .collect::<Vec<_>>();
+        for (i, (kernel, _)) in rows.iter_mut().enumerate() {
+            kernel[i] += REGULARIZATION;
+        }
+        let weights = Self::solve(rows);
+
+        Self {
+            radius,
+            rotation,
+            centers,
+            weights,
+        }
+    }
+
+    pub fn apply(&self, v: Vertex) -> Vertex {
+        let v = matrix::transform(&self.rotation, v);
+        let mut res = self
+            .centers
+            .iter()
+            .zip(&self.weights)
+            .fold(v, |sum, (&c, &w)| {
+                sum + w * Self::kernel(self.radius, v - c)
+            });
+        if res.len() < EPS {
+            return v;
+        }
+        res.normalize();
+
+        res * self.radius
+    }
+}
+
+impl SphereWarp {
+    fn kernel(radius: f64, d: Vertex) -> f64 {
+        (-d.len2() / (WIDTH * radius).powi(2)).exp()
+    }
+
+    // Gaussian elimination with partial pivoting, the right side being vectors
+    fn solve(mut rows: Vec<(Vec<f64>, Vertex)>) -> Vec<Vertex> {
+        let n = rows.len();
+        for col in 0..n {
+            let pivot = (col..n)
+                .max_by(|&i, &j| rows[i].0[col].abs().total_cmp(&rows[j].0[col].abs()))
+                .unwrap_or(col);
+            rows.swap(col, pivot);
+            let (head, tail) = rows.split_at_mut(col + 1);
+            let (pivot_row, pivot_rhs) = &head[col];
+            if pivot_row[col].abs() < EPS {
+                continue;
+            }
+            for (row, rhs) in tail.iter_mut() {
+                let k = row[col] / pivot_row[col];
+                for (a, p) in row.iter_mut().zip(pivot_row).skip(col) {
+                    *a -= k * p;
+                }
+                *rhs -= *pivot_rhs * k;
+            }
+        }
+
+        let mut x = vec![Vertex::new(0., 0., 0.); n];
+        for i in (0..n).rev() {
+            let (row, rhs) = &rows[i];
+            if row[i].abs() < EPS {
+                continue;
+            }
+            let sum = (i + 1..n).fold(*rhs, |sum, j| sum - x[j] * row[j]);
+            x[i] = sum / row[i];
+        }
+
+        x
+    }
+}
@@ -0,0 +1,123 @@
+use super::{bvh::Bvh, kernel::Kernel, object::Object, triangle::Triangle, vertex::Vertex};
+use crate::EPS;
+
+use std::fmt;
+
+const SHOWN_FACES: usize = 5;
+
+// Whether every ray from `center` crosses the surface once, which the
+// spherical projection relies on
+pub struct StarReport {
+    pub center: Vertex,
+    pub back_facing: Vec<usize>,      // faces turned away from the center
+    pub occluded: Vec<usize>,         // faces hidden behind other faces
+    pub kernel_point: Option<Vertex>, // center which passes the check, if the given one fails
+}
+
+impl StarReport {
+    pub fn new(object: &Object, center: Vertex) -> Self {
+        let bvh = Bvh::from_object(object);
+        let kernel = Kernel::new(object);
+        let mut report = Self::check(object, &bvh, &kernel, center);
+        if !report.is_star_shaped() {
+            report.kernel_point = kernel
+                .find_point(center)
+                .filter(|&point| Self::check(object, &bvh, &kernel, point).is_star_shaped());
+        }
+
+        report
+    }
+
+    pub fn is_star_shaped(&self) -> bool {
+        self.back_facing.is_empty() && self.occluded.is_empty()
+    }
+}
+
+impl StarReport {
+    fn check(object: &Object, bvh: &Bvh, kernel: &Kernel, center: Vertex) -> Self {
+        let mut back_facing = Vec::new();
+        let mut occluded = Vec::new();
+        for ind in 0..object.nfaces() {
+            let coords = object.face_coords(ind);
+            let centroid = (coords[0] + coords[1] + coords[2]) / 3.;
+            let dir = centroid - center;
+            let dist = dir.len();
+            if dist < EPS {
+                back_facing.push(ind);
+                continue;
+            }
+            if kernel.normal(ind) * dir < 0. {
+                back_facing.push(ind);
+            }
+
+            let mut is_occluded = false;
+            bvh.query(center, dir, |other| {
+                if other == ind || is_occluded {
+                    return;
+                }
+                let coords = object.face_coords(other);
+                let tri = Triangle::new(coords[0], coords[1], coords[2]);
+                if let Some(int) = tri.intersect(center, centroid) {
+                    let t = (int - center) * dir / dist;
+                    is_occluded = t > EPS && t < dist * (1. - 1e-6);
+                }
+            });
+            if is_occluded {
+                occluded.push(ind);
+            }
+        }
+
+        Self {
+            center,
+            back_facing,
+            occluded,
+            kernel_point: None,
+        }
+    }
+}
+
+impl fmt::Display for StarReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let faces = |faces: &[usize]| {
+            let mut list = faces
+                .iter()
+                .take(SHOWN_FACES)
+                .map(|face| (face + 1).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            if faces.len() > SHOWN_FACES {
+                list.push_str(", ...");
+            }
+            list
+        };
+
+        if self.is_star_shaped() {
+            return write!(f, "модель звёздная относительно центра");
+        }
+        write!(f, "модель не звёздная относительно центра")?;
+        if !self.back_facing.is_empty() {
+            write!(
+                f,
+                "; повёрнуты от центра {} треугольников ({})",
+                self.back_facing.len(),
+                faces(&self.back_facing)
+            )?;
+        }
+        if !self.occluded.is_empty() {
+            write!(
+                f,
+                "; закрыты другими гранями {} треугольников ({})",
+                self.occluded.len(),
+                faces(&self.occluded)
+            )?;
+        }
+        match self.kernel_point {
+            Some(p) => write!(
+                f,
+                "; подходящий центр: ({:.3}, {:.3}, {:.3})",
+                p.x, p.y, p.z
+            ),
+            None => write!(f, "; подходящий центр не найден"),
+        }
+    }
+}
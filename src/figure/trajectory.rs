@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+use super::{
+    matrix::{self, Matrix, IDENTITY},
+    vertex::Vertex,
+};
+use crate::{parallel, EPS};
+
+const SOLVER_STEPS: usize = 1000;
+
+// How the vertexes move from the start model to the result
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Trajectory {
+    // along straight lines
+    #[default]
+    Linear,
+    // the distance to the center and the direction are interpolated separately
+    Spherical,
+    // as rigid as possible: every triangle turns and stretches on its own,
+    // then the vertexes are fitted to the triangles
+    Rigid,
+}
+
+// As-rigid-as-possible interpolation by Alexa et al. The map of each face is
+// split into a rotation, whose angle is interpolated, and a stretch, which is
+// interpolated linearly. The vertexes come from the least squares fit of the
+// edges to the interpolated maps.
+pub struct RigidPath {
+    faces: Vec<RigidFace>,
+    neighbors: Vec<Vec<(usize, f64)>>, // (vertex, weight of the shared edges)
+    degree: Vec<f64>,
+    // linear positions and the fit of the previous frame, close to the next
+    // one while the morph plays
+    last: Mutex<Option<(Vec<Vertex>, Vec<Vertex>)>>,
+}
+
+#[derive(Clone)]
+struct RigidFace {
+    indexes: [usize; 3],
+    weight: f64,
+    rotation: Matrix,
+    stretch: Matrix,
+}
+
+impl RigidPath {
+    // `pairs` are the start and the result positions of the vertexes
+    pub fn new(pairs: &[(Vertex, Vertex)], faces: &[Vec<usize>]) -> Self {
+        let faces = parallel::map(faces, |face| {
+            let indexes = [face[0], face[1], face[2]];
+            let src = indexes.map(|v| pairs[v].0);
+            let dst = indexes.map(|v| pairs[v].1);
+            let area = |t: [Vertex; 3]| ((t[1] - t[0]) ^ (t[2] - t[0])).len() / 2.;
+            match matrix::inverse(&Self::frame(src)) {
+                Some(inverse) => {
+                    let map = matrix::multiply(&Self::frame(dst), &inverse);
+                    let rotation = matrix::orthogonal_fit(map, false);
+                    let stretch = matrix::multiply(&matrix::transpose(&rotation), &map);
+                    RigidFace {
+                        indexes,
+                        weight: area(src) + area(dst),
+                        rotation,
+                        stretch,
+                    }
+                }
+                // a degenerate face just follows its neighbours
+                None => RigidFace {
+                    indexes,
+                    weight: 0.,
+                    rotation: IDENTITY,
+                    stretch: IDENTITY,
+                },
+            }
+        });
+
+        let mut edges = vec![BTreeMap::new(); pairs.len()];
+        for face in faces.iter().filter(|face| face.weight > 0.) {
+            for (k, l) in [(0, 1), (1, 2), (2, 0)] {
+                let (a, b) = (face.indexes[k], face.indexes[l]);
+                *edges[a].entry(b).or_insert(0.) += face.weight;
+                *edges[b].entry(a).or_insert(0.) += face.weight;
+            }
+        }
+        let neighbors = edges
+            .into_iter()
+            .map(|map| map.into_iter().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let degree = neighbors
+            .iter()
+            .map(|nbrs| nbrs.iter().map(|(_, w)| w).sum())
+            .collect();
+
+        Self {
+            faces,
+            neighbors,
+            degree,
+            last: Mutex::new(None),
+        }
+    }
+
+    // Each vertex makes its own share of the way `ratios`, each face the
+    // mean share of its vertexes
+    pub fn interpolation(&self, pairs: &[(Vertex, Vertex)], ratios: &[f64]) -> Vec<Vertex> {
+        let linear = parallel::map_range(pairs.len(), |i| {
+            let (v1, v2) = pairs[i];
+            v1 + (v2 - v1) * ratios[i]
+        });
+
+        let maps = parallel::map(&self.faces, |face| {
+            let [a, b, c] = face.indexes;
+            let ratio = (ratios[a] + ratios[b] + ratios[c]) / 3.;
+            let mut stretch = face.stretch;
+            for (i, row) in stretch.iter_mut().enumerate() {
+                for (j, a) in row.iter_mut().enumerate() {
+                    *a = IDENTITY[i][j] + (*a - IDENTITY[i][j]) * ratio;
+                }
+            }
+            matrix::multiply(&matrix::rotation_power(&face.rotation, ratio), &stretch)
+        });
+        let mut rhs = vec![Vertex::default(); pairs.len()];
+        for (face, map) in self.faces.iter().zip(&maps) {
+            if face.weight == 0. {
+                continue;
+            }
+            for (k, l) in [(0, 1), (1, 2), (2, 0)] {
+                let (a, b) = (face.indexes[k], face.indexes[l]);
+                let edge = matrix::transform(map, pairs[a].0 - pairs[b].0) * face.weight;
+                rhs[a] += edge;
+                rhs[b] -= edge;
+            }
+        }
+
+        // the solve starts from the previous fit moved as the linear positions
+        // moved since, so the vertexes the fit does not constrain stay linear
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        let start = match last.as_ref() {
+            Some((last_linear, last_fit)) if last_fit.len() == pairs.len() => last_fit
+                .iter()
+                .zip(last_linear)
+                .zip(&linear)
+                .map(|((&fit, &before), &now)| fit + (now - before))
+                .collect(),
+            _ => linear.clone(),
+        };
+        let mut vertexes = self.solve(rhs, start);
+        *last = Some((linear.clone(), vertexes.clone()));
+        // the fit is defined up to a shift, the linear one is kept
+        let shift = Vertex::center(&linear) - Vertex::center(&vertexes);
+        for v in vertexes.iter_mut() {
+            *v += shift;
+        }
+
+        vertexes
+    }
+}
+
+impl Clone for RigidPath {
+    fn clone(&self) -> Self {
+        Self {
+            faces: self.faces.clone(),
+            neighbors: self.neighbors.clone(),
+            degree: self.degree.clone(),
+            last: Mutex::new(
+                self.last
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+            ),
+        }
+    }
+}
+
+impl RigidPath {
+    // Two edges and the normal scaled as the edges, so that the map of the
+    // face is the same for all its sizes
+    fn frame(t: [Vertex; 3]) -> Matrix {
+        let (e1, e2) = (t[1] - t[0], t[2] - t[0]);
+        let normal = e1 ^ e2;
+        let normal = normal / normal.len().sqrt().max(EPS);
+
+        matrix::transpose(&[e1, e2, normal].map(|v| [v.x, v.y, v.z]))
+    }
+
+    // Conjugate gradients with the Jacobi preconditioner for the weighted
+    // graph Laplacian, started from `x`
+    fn solve(&self, rhs: Vec<Vertex>, mut x: Vec<Vertex>) -> Vec<Vertex> {
+        let apply = |x: &[Vertex]| {
+            parallel::map_range(x.len(), |i| {
+                self.neighbors[i]
+                    .iter()
+                    .fold(x[i] * self.degree[i], |sum, &(j, w)| sum - x[j] * w)
+            })
+        };
+        let precondition = |r: &[Vertex]| {
+            r.iter()
+                .zip(&self.degree)
+                .map(|(&r, &d)| if d > 0. { r / d } else { Vertex::default() })
+                .collect::<Vec<_>>()
+        };
+        // the three coordinates are independent systems with the same matrix
+        let dot = |a: &[Vertex], b: &[Vertex]| {
+            a.iter().zip(b).fold(Vertex::default(), |sum, (a, b)| {
+                sum + Vertex::new(a.x * b.x, a.y * b.y, a.z * b.z)
+            })
+        };
+        let scale = |v: Vertex, k: Vertex| Vertex::new(v.x * k.x, v.y * k.y, v.z * k.z);
+        let ratio = |a: Vertex, b: Vertex| {
+            let div = |a: f64, b: f64| if b.abs() > 0. { a / b } else { 0. };
+            Vertex::new(div(a.x, b.x), div(a.y, b.y), div(a.z, b.z))
+        };
+
+        let tolerance = 1e-20 * dot(&rhs, &rhs).len();
+        let ax = apply(&x);
+        let mut r = rhs
+            .iter()
+            .zip(&ax)
+            .map(|(&b, &ax)| b - ax)
+            .collect::<Vec<_>>();
+        let mut z = precondition(&r);
+        let mut p = z.clone();
+        let mut rz = dot(&r, &z);
+        for _ in 0..SOLVER_STEPS {
+            if dot(&r, &r).len() <= tolerance {
+                break;
+            }
+            let ap = apply(&p);
+            let alpha = ratio(rz, dot(&p, &ap));
+            for i in 0..x.len() {
+                x[i] += scale(p[i], alpha);
+                r[i] -= scale(ap[i], alpha);
+            }
+            z = precondition(&r);
+            let rz_next = dot(&r, &z);
+            let beta = ratio(rz_next, rz);
+            rz = rz_next;
+            for i in 0..p.len() {
+                p[i] = z[i] + scale(p[i], beta);
+            }
+        }
+
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warm_start_keeps_the_fit() {
+        // a tetrahedron turning and growing, the last vertex is in no face
+        let start = [
+            Vertex::new(0., 0., 0.),
+            Vertex::new(1., 0., 0.),
+            Vertex::new(0., 1., 0.),
+            Vertex::new(0., 0., 1.),
+            Vertex::new(5., 5., 5.),
+        ];
+        let result = [
+            Vertex::new(0., 0., 0.),
+            Vertex::new(0., 2., 0.),
+            Vertex::new(-2., 0., 0.),
+            Vertex::new(0., 0., 3.),
+            Vertex::new(-5., 5., 0.),
+        ];
+        let pairs = start.into_iter().zip(result).collect::<Vec<_>>();
+        let faces = [[0, 2, 1], [0, 1, 3], [1, 2, 3], [0, 3, 2]].map(Vec::from);
+
+        let played = RigidPath::new(&pairs, &faces);
+        for ratio in [0.1, 0.9, 0.4] {
+            let ratios = vec![ratio; pairs.len()];
+            let warm = played.interpolation(&pairs, &ratios);
+            let cold = RigidPath::new(&pairs, &faces).interpolation(&pairs, &ratios);
+            for (w, c) in warm.iter().zip(&cold) {
+                assert!((*w - *c).len() < 1e-6);
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use super::vertex::Vertex;
+use super::vertex::{TexCoord, Vertex};
+use crate::color::Color;
 use crate::EPS;
 
 pub struct Triangle {
@@ -42,7 +43,7 @@ impl Triangle {
         }
     }
 
-    pub fn normal_inside(&self, v: Vertex, normals: Vec<Vertex>) -> Vertex {
+    pub fn barycentric(&self, v: Vertex) -> (f64, f64, f64) {
         let av = v - self.a;
         let bv = v - self.b;
         let ab = self.b - self.a;
@@ -54,10 +55,44 @@ impl Triangle {
         let s_acv = (av ^ ac).len() / 2.;
         let s_abc = (ab ^ ac).len() / 2.;
 
-        let t1 = s_bcv / s_abc;
-        let t2 = s_acv / s_abc;
-        let t3 = s_abv / s_abc;
+        (s_bcv / s_abc, s_acv / s_abc, s_abv / s_abc)
+    }
+
+    pub fn normal_inside(&self, v: Vertex, normals: Vec<Vertex>) -> Vertex {
+        let (t1, t2, t3) = self.barycentric(v);
 
         (normals[0] * t1) + (normals[1] * t2) + (normals[2] * t3)
     }
+
+    pub fn texcoord_inside(&self, v: Vertex, texcoords: Vec<TexCoord>) -> TexCoord {
+        let (t1, t2, t3) = self.barycentric(v);
+
+        (
+            texcoords[0].0 * t1 + texcoords[1].0 * t2 + texcoords[2].0 * t3,
+            texcoords[0].1 * t1 + texcoords[1].1 * t2 + texcoords[2].1 * t3,
+        )
+    }
+
+    pub fn weight_inside(&self, v: Vertex, weights: [f64; 3]) -> f64 {
+        let (t1, t2, t3) = self.barycentric(v);
+
+        weights[0] * t1 + weights[1] * t2 + weights[2] * t3
+    }
+
+    pub fn color_inside(&self, v: Vertex, colors: Vec<Color>) -> Color {
+        let (t1, t2, t3) = self.barycentric(v);
+        let (c1, c2, c3) = (
+            colors[0].to_array(),
+            colors[1].to_array(),
+            colors[2].to_array(),
+        );
+        let mut res = [0; 4];
+        for i in 0..4 {
+            res[i] = (c1[i] as f64 * t1 + c2[i] as f64 * t2 + c3[i] as f64 * t3)
+                .round()
+                .clamp(0., 255.) as u8;
+        }
+
+        Color::new(res)
+    }
 }
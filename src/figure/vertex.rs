@@ -1,9 +1,11 @@
-use crate::DEFAULT_SCALE;
+use crate::{DEFAULT_SCALE, EPS};
 
 use std::cmp::Ordering;
 use std::ops::{Add, AddAssign, BitXor, Div, DivAssign, Mul, Neg, Sub, SubAssign};
 
-#[derive(Clone, Copy, Default, PartialEq, PartialOrd)]
+pub type TexCoord = (f64, f64); // (u, v)
+
+#[derive(Clone, Copy, Default, PartialEq)]
 pub struct Vertex {
     pub x: f64,
     pub y: f64,
@@ -32,6 +34,28 @@ impl Vertex {
         dir * (radius / dir.len())
     }
 
+    // Interpolates the length and the direction around the origin separately,
+    // straight through if the directions are opposite or a vector is zero
+    pub fn slerp(self, other: Vertex, t: f64) -> Vertex {
+        let (len1, len2) = (self.len(), other.len());
+        if len1 < EPS || len2 < EPS {
+            return self + (other - self) * t;
+        }
+        let (a, b) = (self / len1, other / len2);
+        let angle = (a * b).clamp(-1., 1.).acos();
+        let sin = angle.sin();
+        if sin < 1e-6 {
+            return if angle < 1. {
+                (a + (b - a) * t) * (len1 + (len2 - len1) * t)
+            } else {
+                self + (other - self) * t
+            };
+        }
+        let dir = a * (((1. - t) * angle).sin() / sin) + b * ((t * angle).sin() / sin);
+
+        dir * (len1 + (len2 - len1) * t)
+    }
+
     pub fn round(&mut self) {
         self.x = self.x.round();
         self.y = self.y.round();
@@ -45,12 +69,12 @@ impl Vertex {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
-    pub fn center(vertexes: &Vec<Vertex>) -> Vertex {
+    pub fn center(vertexes: &[Vertex]) -> Vertex {
         let mut center = Vertex::default();
         for vertex in vertexes.iter() {
             center += *vertex;
         }
-        if vertexes.len() > 0 {
+        if !vertexes.is_empty() {
             center /= vertexes.len();
         }
 
@@ -209,6 +233,12 @@ impl Div<f64> for Vertex {
 
 impl Eq for Vertex {}
 
+impl PartialOrd for Vertex {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Ord for Vertex {
     fn cmp(&self, other: &Self) -> Ordering {
         (self.x, self.y, self.z)
@@ -0,0 +1,17 @@
+#![allow(non_snake_case)]
+
+pub mod canvas;
+pub mod color;
+pub mod consts;
+pub mod figure;
+pub mod playback;
+pub mod texture;
+
+mod parallel;
+
+pub use canvas::Canvas;
+pub use color::Color;
+pub use consts::*;
+pub use figure::{
+    merged_object::MergedObject, object::Object, projection::Projection, vertex::Vertex,
+};
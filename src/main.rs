@@ -2,12 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use eframe::egui;
+use Morphing::{canvas, color, consts, figure, playback, texture};
 
 mod app;
-mod canvas;
-mod color;
-mod consts;
-mod figure;
 
 use app::Painting;
 use consts::*;
@@ -0,0 +1,101 @@
+// Maps run on all cores with the `parallel` feature and on the calling thread
+// otherwise. Results keep the order of the input either way.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+#[cfg(feature = "parallel")]
+pub fn map<T, U, F>(items: &[T], f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync + Send,
+{
+    items.par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn map<T, U, F>(items: &[T], f: F) -> Vec<U>
+where
+    F: Fn(&T) -> U,
+{
+    items.iter().map(f).collect()
+}
+
+#[cfg(feature = "parallel")]
+pub fn map_range<U, F>(n: usize, f: F) -> Vec<U>
+where
+    U: Send,
+    F: Fn(usize) -> U + Sync + Send,
+{
+    (0..n).into_par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn map_range<U, F>(n: usize, f: F) -> Vec<U>
+where
+    F: Fn(usize) -> U,
+{
+    (0..n).map(f).collect()
+}
+
+#[cfg(feature = "parallel")]
+pub fn try_map<T, U, E, F>(items: &[T], f: F) -> Result<Vec<U>, E>
+where
+    T: Sync,
+    U: Send,
+    E: Send,
+    F: Fn(&T) -> Result<U, E> + Sync + Send,
+{
+    // collecting into `Result` directly returns whichever error comes first in
+    // time, the serial version returns the one with the lowest index
+    let results = items.par_iter().map(f).collect::<Vec<_>>();
+    results.into_iter().collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn try_map<T, U, E, F>(items: &[T], f: F) -> Result<Vec<U>, E>
+where
+    F: Fn(&T) -> Result<U, E>,
+{
+    items.iter().map(f).collect()
+}
+
+// Run with and without `--features parallel`: both must give what the serial
+// iterators give
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: &u64) -> u64 {
+        x * x
+    }
+
+    fn checked(x: &u64) -> Result<u64, u64> {
+        if x % 7 == 3 {
+            Err(*x)
+        } else {
+            Ok(x * x)
+        }
+    }
+
+    #[test]
+    fn keeps_order() {
+        let items = (0..10_000).collect::<Vec<u64>>();
+        let expected = items.iter().map(square).collect::<Vec<_>>();
+        assert_eq!(map(&items, square), expected);
+        assert_eq!(map_range(items.len(), |i| square(&items[i])), expected);
+        assert_eq!(try_map(&items[..3], checked), Ok(vec![0, 1, 4]));
+    }
+
+    #[test]
+    fn first_error() {
+        // every seventh item fails, the one with the lowest index is reported on every run
+        for _ in 0..20 {
+            let items = (0..10_000).rev().collect::<Vec<u64>>();
+            let expected = items.iter().map(checked).collect::<Result<Vec<_>, _>>();
+            assert_eq!(try_map(&items, checked), expected);
+            assert_eq!(expected, Err(9_999));
+        }
+    }
+}
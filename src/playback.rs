@@ -0,0 +1,374 @@
+use crate::EPS;
+
+const BEZIER_STEPS: usize = 50;
+const INVERSE_SAMPLES: usize = 1000;
+const KEYFRAME_SAMPLES: usize = 60; // per pass of a curved easing
+
+// Shape of the morph in time
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EasingKind {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    // cubic Bézier curve from (0, 0) to (1, 1) as in CSS
+    Bezier,
+    // broken line through the user's keyframes
+    Keyframes,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Easing {
+    pub kind: EasingKind,
+    pub bezier: [f64; 4],           // control points (x1, y1, x2, y2)
+    pub keyframes: Vec<(f64, f64)>, // (time, ratio) between the fixed ends (0, 0) and (1, 1)
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Self {
+            kind: EasingKind::default(),
+            bezier: [0.25, 0.1, 0.25, 1.],
+            keyframes: vec![(0.5, 0.5)],
+        }
+    }
+}
+
+impl Easing {
+    // Ratio of the morph at the share `t` of its duration
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        match self.kind {
+            EasingKind::Linear => t,
+            EasingKind::EaseIn => t * t * t,
+            EasingKind::EaseOut => 1. - (1. - t).powi(3),
+            EasingKind::EaseInOut => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    1. - (2. - 2. * t).powi(3) / 2.
+                }
+            }
+            EasingKind::Bezier => self.bezier_at(t),
+            EasingKind::Keyframes => self.keyframes_at(t),
+        }
+    }
+
+    // Share of the duration where the ratio is the closest to `ratio`,
+    // the earliest one if the curve comes back
+    pub fn time_of(&self, ratio: f64) -> f64 {
+        let mut best = (f64::MAX, 0.);
+        for i in 0..=INVERSE_SAMPLES {
+            let t = i as f64 / INVERSE_SAMPLES as f64;
+            let d = (self.apply(t) - ratio).abs();
+            if d < best.0 - EPS {
+                best = (d, t);
+            }
+        }
+
+        best.1
+    }
+}
+
+impl Easing {
+    // The parameter of the curve is found by bisection, `x` grows as both
+    // control points lie between 0 and 1
+    fn bezier_at(&self, t: f64) -> f64 {
+        let [x1, y1, x2, y2] = self.bezier;
+        let coord = |a: f64, b: f64, s: f64| {
+            3. * (1. - s) * (1. - s) * s * a + 3. * (1. - s) * s * s * b + s * s * s
+        };
+        let (x1, x2) = (x1.clamp(0., 1.), x2.clamp(0., 1.));
+        let (mut lo, mut hi) = (0., 1.);
+        for _ in 0..BEZIER_STEPS {
+            let mid = (lo + hi) / 2.;
+            if coord(x1, x2, mid) < t {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        coord(y1, y2, (lo + hi) / 2.)
+    }
+
+    fn keyframes_at(&self, t: f64) -> f64 {
+        let mut points = self.keyframes.clone();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points.insert(0, (0., 0.));
+        points.push((1., 1.));
+
+        let i = points
+            .partition_point(|p| p.0 < t)
+            .clamp(1, points.len() - 1);
+        let ((t1, r1), (t2, r2)) = (points[i - 1], points[i]);
+        if t2 - t1 < EPS {
+            return r2;
+        }
+
+        r1 + (r2 - r1) * (t - t1) / (t2 - t1)
+    }
+}
+
+// What happens when a pass ends
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Repeat {
+    #[default]
+    Once,
+    Loop,
+    PingPong,
+}
+
+// Time of the morph advanced by the real time between frames, so the speed
+// does not depend on the frame rate
+#[derive(Clone, Debug)]
+pub struct Playback {
+    pub duration: f64, // of a pass, in seconds
+    pub easing: Easing,
+    pub repeat: Repeat,
+    time: f64, // share of the pass, from 0 to 1
+    is_backward: bool,
+    is_playing: bool,
+}
+
+impl Playback {
+    pub fn new(duration: f64) -> Self {
+        Self {
+            duration,
+            easing: Easing::default(),
+            repeat: Repeat::default(),
+            time: 0.,
+            is_backward: false,
+            is_playing: false,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    pub fn play_from_start(&mut self) {
+        self.time = 0.;
+        self.is_backward = false;
+        self.is_playing = true;
+    }
+
+    // A finished single pass starts again
+    pub fn play(&mut self) {
+        if self.repeat == Repeat::Once && self.time >= 1. {
+            self.time = 0.;
+        }
+        self.is_playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.is_playing = false;
+    }
+
+    pub fn advance(&mut self, seconds: f64) {
+        if !self.is_playing {
+            return;
+        }
+        let step = seconds / self.duration.max(EPS);
+        self.time += if self.is_backward { -step } else { step };
+        match self.repeat {
+            Repeat::Once if self.time >= 1. => {
+                self.time = 1.;
+                self.is_playing = false;
+            }
+            Repeat::Loop if self.time >= 1. => self.time = self.time.fract(),
+            Repeat::PingPong if self.time >= 1. => {
+                self.time = (2. - self.time).max(0.);
+                self.is_backward = true;
+            }
+            Repeat::PingPong if self.time <= 0. => {
+                self.time = (-self.time).min(1.);
+                self.is_backward = false;
+            }
+            // a pass back left after switching from the ping-pong
+            _ if self.time <= 0. => {
+                self.time = 0.;
+                self.is_backward = false;
+            }
+            _ => {}
+        }
+    }
+
+    // Morph ratio from the start model to the result
+    pub fn ratio(&self) -> f64 {
+        self.easing.apply(self.time)
+    }
+
+    // (seconds, ratio) through a pass, and the way back for the ping-pong,
+    // for players which interpolate linearly between them
+    pub fn keyframes(&self) -> Vec<(f64, f64)> {
+        let n = match self.easing.kind {
+            EasingKind::Linear => 1,
+            _ => KEYFRAME_SAMPLES,
+        };
+        let mut keyframes = (0..=n)
+            .map(|i| {
+                let t = i as f64 / n as f64;
+                (t * self.duration, self.easing.apply(t))
+            })
+            .collect::<Vec<_>>();
+        if self.repeat == Repeat::PingPong {
+            let back = keyframes
+                .iter()
+                .rev()
+                .skip(1)
+                .map(|&(time, ratio)| (2. * self.duration - time, ratio))
+                .collect::<Vec<_>>();
+            keyframes.extend(back);
+        }
+
+        keyframes
+    }
+
+    // Stops at the first moment with the given ratio
+    pub fn seek(&mut self, ratio: f64) {
+        self.time = self.easing.time_of(ratio);
+        self.is_playing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [EasingKind; 6] = [
+        EasingKind::Linear,
+        EasingKind::EaseIn,
+        EasingKind::EaseOut,
+        EasingKind::EaseInOut,
+        EasingKind::Bezier,
+        EasingKind::Keyframes,
+    ];
+
+    fn easing(kind: EasingKind) -> Easing {
+        Easing {
+            kind,
+            keyframes: vec![(0.7, 0.2), (0.3, 0.6)],
+            ..Easing::default()
+        }
+    }
+
+    #[test]
+    fn easing_ends() {
+        for kind in KINDS {
+            let easing = easing(kind);
+            assert!(easing.apply(0.).abs() < 1e-6, "{kind:?}");
+            assert!((easing.apply(1.) - 1.).abs() < 1e-6, "{kind:?}");
+            // time outside of the pass is clamped
+            assert_eq!(easing.apply(-1.), easing.apply(0.), "{kind:?}");
+            assert_eq!(easing.apply(2.), easing.apply(1.), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn keyframes_once() {
+        let mut playback = Playback::new(3.);
+        assert_eq!(playback.keyframes(), vec![(0., 0.), (3., 1.)]);
+
+        playback.easing = easing(EasingKind::EaseInOut);
+        let keyframes = playback.keyframes();
+        assert_eq!(keyframes.len(), KEYFRAME_SAMPLES + 1);
+        assert_eq!(keyframes[0], (0., 0.));
+        assert_eq!(keyframes[KEYFRAME_SAMPLES], (3., 1.));
+        assert!(keyframes.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn keyframes_ping_pong() {
+        let mut playback = Playback::new(2.);
+        playback.repeat = Repeat::PingPong;
+        assert_eq!(playback.keyframes(), vec![(0., 0.), (2., 1.), (4., 0.)]);
+
+        playback.easing = easing(EasingKind::EaseIn);
+        let keyframes = playback.keyframes();
+        let n = KEYFRAME_SAMPLES;
+        assert_eq!(keyframes.len(), 2 * n + 1);
+        assert_eq!(keyframes[n], (2., 1.));
+        assert_eq!(keyframes[2 * n], (4., 0.));
+        assert!(keyframes.windows(2).all(|w| w[0].0 < w[1].0));
+        // the way back mirrors the way there
+        for i in 0..=n {
+            let (there, back) = (keyframes[i], keyframes[2 * n - i]);
+            assert!((there.0 + back.0 - 4.).abs() < 1e-9);
+            assert_eq!(there.1, back.1);
+        }
+    }
+
+    // Linear pass of 2 seconds, steps of a quarter are exact
+    fn started(repeat: Repeat) -> Playback {
+        let mut playback = Playback::new(2.);
+        playback.repeat = repeat;
+        playback.play_from_start();
+        playback
+    }
+
+    #[test]
+    fn loop_wraps() {
+        let mut playback = started(Repeat::Loop);
+        playback.advance(2.5);
+        assert_eq!(playback.ratio(), 0.25);
+        assert!(playback.is_playing());
+    }
+
+    #[test]
+    fn ping_pong_turns_at_both_ends() {
+        let mut playback = started(Repeat::PingPong);
+        playback.advance(3.);
+        assert_eq!(playback.ratio(), 0.5);
+        assert!(playback.is_backward);
+        playback.advance(2.);
+        assert_eq!(playback.ratio(), 0.5);
+        assert!(!playback.is_backward);
+        assert!(playback.is_playing());
+    }
+
+    #[test]
+    fn once_stops_at_the_end() {
+        let mut playback = started(Repeat::Once);
+        playback.advance(3.);
+        assert_eq!(playback.ratio(), 1.);
+        assert!(!playback.is_playing());
+        playback.advance(1.);
+        assert_eq!(playback.ratio(), 1.);
+
+        // a finished pass starts again
+        playback.play();
+        playback.advance(0.5);
+        assert_eq!(playback.ratio(), 0.25);
+    }
+
+    #[test]
+    fn loop_after_ping_pong_finishes_the_pass_back() {
+        let mut playback = started(Repeat::PingPong);
+        playback.advance(3.);
+        playback.repeat = Repeat::Loop;
+        playback.advance(0.5);
+        assert_eq!(playback.ratio(), 0.25);
+        assert!(playback.is_backward);
+        playback.advance(1.);
+        assert_eq!(playback.ratio(), 0.);
+        assert!(!playback.is_backward);
+        playback.advance(0.5);
+        assert_eq!(playback.ratio(), 0.25);
+    }
+
+    #[test]
+    fn seek_pauses() {
+        let mut playback = started(Repeat::Loop);
+        playback.seek(0.75);
+        assert_eq!(playback.ratio(), 0.75);
+        assert!(!playback.is_playing());
+        playback.advance(1.);
+        assert_eq!(playback.ratio(), 0.75);
+
+        playback.play();
+        playback.advance(1.);
+        assert_eq!(playback.ratio(), 0.25);
+    }
+}
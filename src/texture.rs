@@ -0,0 +1,126 @@
+use crate::color::Color;
+use crate::figure::vertex::TexCoord;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub type SharedTexture = Arc<Texture>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Wrap {
+    #[default]
+    Repeat,
+    Clamp,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+pub struct Texture {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,         // RGBA
+    path: Option<PathBuf>, // absolute path of the image it was loaded from
+    is_opaque: bool,
+}
+
+impl Texture {
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Self {
+        let is_opaque = data.chunks_exact(4).all(|texel| texel[3] == 255);
+        Self {
+            width,
+            height,
+            data,
+            path: None,
+            is_opaque,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> image::ImageResult<Self> {
+        let image = image::open(&path)?.into_rgba8();
+
+        Ok(Self {
+            path: std::path::absolute(path).ok(),
+            ..Self::new(image.width(), image.height(), image.into_raw())
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // No texel is translucent
+    pub fn is_opaque(&self) -> bool {
+        self.is_opaque
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn sample(&self, uv: TexCoord, sampler: Sampler) -> Color {
+        // `v` grows upwards in OBJ, while image rows go from the top
+        let x = uv.0 * self.width as f64;
+        let y = (1. - uv.1) * self.height as f64;
+        match sampler.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64, sampler.wrap),
+            Filter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = Color::interpolation(
+                    self.texel(x0, y0, sampler.wrap),
+                    self.texel(x0 + 1, y0, sampler.wrap),
+                    tx,
+                );
+                let bottom = Color::interpolation(
+                    self.texel(x0, y0 + 1, sampler.wrap),
+                    self.texel(x0 + 1, y0 + 1, sampler.wrap),
+                    tx,
+                );
+                Color::interpolation(top, bottom, ty)
+            }
+        }
+    }
+}
+
+impl Texture {
+    fn wrap(coord: i64, size: u32, wrap: Wrap) -> usize {
+        match wrap {
+            Wrap::Repeat => coord.rem_euclid(size as i64) as usize,
+            Wrap::Clamp => coord.clamp(0, size as i64 - 1) as usize,
+        }
+    }
+
+    fn texel(&self, x: i64, y: i64, wrap: Wrap) -> Color {
+        if self.width == 0 || self.height == 0 {
+            return Color::new([0; 4]);
+        }
+        let x = Self::wrap(x, self.width, wrap);
+        let y = Self::wrap(y, self.height, wrap);
+        let pixel = 4 * (x + y * self.width as usize);
+
+        Color::new([
+            self.data[pixel],
+            self.data[pixel + 1],
+            self.data[pixel + 2],
+            self.data[pixel + 3],
+        ])
+    }
+}